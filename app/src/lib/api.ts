@@ -213,7 +213,11 @@ export async function getCommitContent(
       headers: { Authorization: jwt_token },
     })
     .then((res) => {
-      return res.data.result
+      const content = res.data.result.content
+      // chat prompts come back as a list of messages
+      return typeof content === 'string'
+        ? content
+        : JSON.stringify(content, null, 2)
     })
     .catch((err) => {
       if (axios.isAxiosError(err)) {
@@ -12,7 +12,84 @@ CREATE TABLE prompts (
     updated_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
     user_id        BIGINT,
     file_key       VARCHAR(100) NOT NULL,
-    org_id         BIGINT
+    org_id         BIGINT,
+    name           VARCHAR(255) NOT NULL DEFAULT '',
+    template_engine VARCHAR(16) NOT NULL DEFAULT 'simple',  -- simple / jinja
+    content_type   VARCHAR(16) NOT NULL DEFAULT 'plain'    -- 提交未指定时的内容类型: plain / markdown / json / yaml / chat
+);
+
+CREATE TABLE prompt_versions (
+    id             BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    prompt_id      BIGINT UNSIGNED NOT NULL,
+    version        VARCHAR(32) NOT NULL,
+    base_commit    VARCHAR(64) NULL,             -- 分支来源提交，版本的第一个提交以它为父提交
+    created_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    UNIQUE KEY uk_prompt_version (prompt_id, version)
+);
+
+CREATE TABLE prompt_commits (
+    id             BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    prompt_id      BIGINT UNSIGNED NOT NULL,
+    version        VARCHAR(32) NOT NULL,
+    commit_id      VARCHAR(64) NOT NULL UNIQUE,  -- 提交内容存放在 <file_key>/<version>/<commit_id>
+    author         VARCHAR(255) NOT NULL,
+    desp           TEXT NOT NULL,
+    content_type   VARCHAR(16) NOT NULL DEFAULT 'plain',  -- plain / markdown / json / yaml / chat(JSON 消息列表)
+    variables      JSON NULL,                    -- 变量声明: [{name, type, required, default, description}]
+    model_config   JSON NULL,                    -- 模型参数: {model, temperature, max_tokens, stop, response_format}
+    output_schema  JSON NULL,                    -- 模型输出需满足的 JSON Schema
+    parents        JSON NULL,                    -- 父提交 commit_id 列表，合并提交有两个
+    created_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX idx_prompt_version (prompt_id, version)
+);
+
+CREATE TABLE prompt_includes (
+    id             BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    prompt_id      BIGINT UNSIGNED NOT NULL,   -- 包含方
+    version        VARCHAR(32) NOT NULL,
+    commit_id      VARCHAR(64) NOT NULL,
+    included_id    BIGINT UNSIGNED NOT NULL,   -- 被包含的提示词
+    reference      VARCHAR(160) NOT NULL,      -- 42 / 42@prod / 42@v1/<commit_id>
+    created_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX idx_included (included_id),
+    INDEX idx_prompt (prompt_id)
+);
+
+CREATE TABLE prompt_labels (
+    id             BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    prompt_id      BIGINT UNSIGNED NOT NULL,
+    name           VARCHAR(64) NOT NULL,       -- dev / staging / prod ...，latest 存放在 prompts 表
+    version        VARCHAR(32) NOT NULL,
+    commit_id      VARCHAR(64) NOT NULL,
+    created_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    UNIQUE KEY uk_prompt_label (prompt_id, name)
+);
+
+CREATE TABLE prompt_promotions (
+    id             BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    prompt_id      BIGINT UNSIGNED NOT NULL,
+    label          VARCHAR(64) NOT NULL,
+    prev_version   VARCHAR(32),
+    prev_commit    VARCHAR(64),
+    version        VARCHAR(32),                -- 标签被删除时为 NULL
+    commit_id      VARCHAR(64),
+    actor          VARCHAR(255) NOT NULL,
+    reason         TEXT,
+    created_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    INDEX idx_prompt_label_time (prompt_id, label, created_at)
+);
+
+CREATE TABLE prompt_permissions (
+    id             BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    prompt_id      BIGINT UNSIGNED NOT NULL,
+    subject_type   VARCHAR(8) NOT NULL,        -- user / org
+    subject_id     BIGINT NOT NULL,
+    role           VARCHAR(16) NOT NULL,       -- viewer / editor / maintainer / owner
+    created_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE KEY uk_prompt_subject (prompt_id, subject_type, subject_id),
+    INDEX idx_subject (subject_type, subject_id)
 );
 
 CREATE TABLE organizations (
@@ -0,0 +1,120 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::{Algorithm, DiffOp, capture_diff_slices};
+
+use super::ContentError;
+use crate::diff::{self, DiffOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    /// optional participant name, as in the OpenAI chat format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Validates every message, reporting the index of the first invalid one.
+pub fn parse(messages: Vec<Value>) -> Result<Vec<Message>, ContentError> {
+    if messages.is_empty() {
+        return Err(ContentError::new("a chat needs at least one message"));
+    }
+    messages
+        .into_iter()
+        .enumerate()
+        .map(|(i, m)| {
+            serde_json::from_value(m).map_err(|e| ContentError::new(format!("message {i}: {e}")))
+        })
+        .collect()
+}
+
+/// Parses a JSON list of messages.
+pub fn from_text(text: &str) -> Result<Vec<Message>, ContentError> {
+    match serde_json::from_str(text)? {
+        Value::Array(messages) => parse(messages),
+        _ => Err(ContentError::new("a chat must be a list of messages")),
+    }
+}
+
+/// Writes whole messages numbered from `first`, every line prefixed with `sign`.
+fn write_messages(res: &mut String, sign: char, first: usize, messages: &[Message]) {
+    for (i, m) in messages.iter().enumerate() {
+        let _ = writeln!(res, "{sign}@@ #{} {} @@", first + i, m.role.as_str());
+        for line in m.content.lines() {
+            let _ = writeln!(res, "{sign}{line}");
+        }
+    }
+}
+
+/// Diffs two chats message by message. Messages are aligned first, then the
+/// bodies of changed messages are diffed line by line under a
+/// `@@ #<index> <role> @@` header.
+pub fn diff(left: &[Message], right: &[Message], opts: &DiffOptions) -> String {
+    let mut res = String::new();
+    for op in capture_diff_slices(Algorithm::Myers, left, right) {
+        let (old, new) = (op.old_range(), op.new_range());
+        match op {
+            DiffOp::Equal { .. } => write_messages(&mut res, ' ', new.start, &right[new]),
+            DiffOp::Delete { .. } => write_messages(&mut res, '-', old.start, &left[old]),
+            DiffOp::Insert { .. } => write_messages(&mut res, '+', new.start, &right[new]),
+            DiffOp::Replace { .. } => {
+                // pair up replaced messages, the rest were removed or added
+                let paired = old.len().min(new.len());
+                for (i, (l, r)) in left[old.clone()]
+                    .iter()
+                    .zip(&right[new.clone()])
+                    .enumerate()
+                {
+                    let idx = new.start + i;
+                    if l.role == r.role {
+                        let _ = writeln!(res, " @@ #{idx} {} @@", r.role.as_str());
+                    } else {
+                        let _ = writeln!(
+                            res,
+                            " @@ #{idx} {} -> {} @@",
+                            l.role.as_str(),
+                            r.role.as_str()
+                        );
+                    }
+                    diff::write_text(&mut res, &l.content, &r.content, opts);
+                }
+                write_messages(
+                    &mut res,
+                    '-',
+                    old.start + paired,
+                    &left[old.start + paired..old.end],
+                );
+                write_messages(
+                    &mut res,
+                    '+',
+                    new.start + paired,
+                    &right[new.start + paired..new.end],
+                );
+            }
+        }
+    }
+    res
+}
@@ -0,0 +1,41 @@
+use super::ContentError;
+
+/// An opening ``` or ~~~ fence, indented by at most three spaces.
+fn fence(line: &str) -> Option<(usize, char, usize)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.chars().take_while(|c| *c == marker).count();
+    (len >= 3).then_some((indent, marker, len))
+}
+
+/// Markdown always parses, but a code fence left open swallows the rest of the
+/// document.
+pub fn validate(text: &str) -> Result<(), ContentError> {
+    let mut open: Option<(usize, usize, char, usize)> = None;
+    for (idx, line) in text.lines().enumerate() {
+        let Some((indent, marker, len)) = fence(line) else {
+            continue;
+        };
+        match open {
+            None => open = Some((idx + 1, indent + 1, marker, len)),
+            // a closing fence uses the same marker, at least as long, and
+            // carries no info string
+            Some((_, _, m, l))
+                if marker == m && len >= l && line.trim_matches(' ').len() == len =>
+            {
+                open = None
+            }
+            Some(_) => {}
+        }
+    }
+    match open {
+        Some((line, column, _, _)) => {
+            Err(ContentError::at("code fence is never closed", line, column))
+        }
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,221 @@
+//! Formats of commit content. `plain`, `markdown`, `json` and `yaml` content
+//! is stored and returned as text, `chat` content is an ordered list of
+//! role-tagged messages. Everything but `plain` is validated on commit.
+
+pub mod chat;
+mod markdown;
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use chat::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    #[default]
+    Plain,
+    Markdown,
+    Json,
+    Yaml,
+    Chat,
+}
+
+impl ContentType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentType::Plain => "plain",
+            ContentType::Markdown => "markdown",
+            ContentType::Json => "json",
+            ContentType::Yaml => "yaml",
+            ContentType::Chat => "chat",
+        }
+    }
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "plain" => Some(ContentType::Plain),
+            "markdown" => Some(ContentType::Markdown),
+            "json" => Some(ContentType::Json),
+            "yaml" => Some(ContentType::Yaml),
+            "chat" => Some(ContentType::Chat),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where and why content does not parse as its type. Lines and columns start
+/// at 1.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentError {
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl ContentError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+    fn at(message: impl Into<String>, line: usize, column: usize) -> Self {
+        Self {
+            message: message.into(),
+            line: Some(line),
+            column: Some(column),
+        }
+    }
+    /// serde_json and serde_yaml append the location to their messages.
+    fn from_parser(message: String, line: usize, column: usize) -> Self {
+        let suffix = format!(" at line {line} column {column}");
+        let message = message
+            .strip_suffix(&suffix)
+            .map(str::to_string)
+            .unwrap_or(message);
+        Self::at(message, line, column)
+    }
+}
+
+impl From<serde_json::Error> for ContentError {
+    fn from(e: serde_json::Error) -> Self {
+        let (line, column) = (e.line(), e.column());
+        Self::from_parser(e.to_string(), line, column)
+    }
+}
+
+impl From<serde_yaml_ng::Error> for ContentError {
+    fn from(e: serde_yaml_ng::Error) -> Self {
+        match e.location() {
+            Some(loc) => Self::from_parser(e.to_string(), loc.line(), loc.column()),
+            None => Self::new(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "line {line}, column {column}: {}", self.message)
+            }
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Checks that `text` parses as `content_type`.
+pub fn validate(content_type: ContentType, text: &str) -> Result<(), ContentError> {
+    match content_type {
+        ContentType::Plain => Ok(()),
+        ContentType::Markdown => markdown::validate(text),
+        ContentType::Json => serde_json::from_str::<Value>(text)
+            .map(|_| ())
+            .map_err(ContentError::from),
+        ContentType::Yaml => serde_yaml_ng::from_str::<serde_yaml_ng::Value>(text)
+            .map(|_| ())
+            .map_err(ContentError::from),
+        ContentType::Chat => chat::from_text(text).map(|_| ()),
+    }
+}
+
+/// Content of a commit as exchanged with clients: text, or the messages of a
+/// chat prompt.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Chat(Vec<Message>),
+}
+
+impl Content {
+    /// Reads and validates a request body value. Without an explicit type an
+    /// array is a chat and a string takes the prompt's `default` type. A chat
+    /// may also be given as a JSON string and a json document as a value.
+    pub fn from_value(
+        content_type: Option<ContentType>,
+        default: ContentType,
+        value: Value,
+    ) -> Result<(ContentType, Self), ContentError> {
+        let content_type = match (content_type, &value) {
+            (Some(t), _) => t,
+            (None, Value::Array(_)) => ContentType::Chat,
+            (None, _) => default,
+        };
+        let invalid = |e: ContentError| ContentError {
+            message: format!("Invalid {content_type} content, {}", e.message),
+            ..e
+        };
+        let content = match (content_type, value) {
+            (ContentType::Chat, Value::Array(messages)) => {
+                Content::Chat(chat::parse(messages).map_err(invalid)?)
+            }
+            (ContentType::Chat, Value::String(s)) => {
+                Content::Chat(chat::from_text(&s).map_err(invalid)?)
+            }
+            (ContentType::Chat, _) => {
+                return Err(ContentError::new("Chat content must be a list of messages"));
+            }
+            (_, Value::String(s)) => {
+                validate(content_type, &s).map_err(invalid)?;
+                Content::Text(s)
+            }
+            (ContentType::Json, value) => {
+                Content::Text(serde_json::to_string_pretty(&value).unwrap())
+            }
+            (_, _) => {
+                return Err(ContentError::new(format!(
+                    "Content of type {content_type} must be a string"
+                )));
+            }
+        };
+        Ok((content_type, content))
+    }
+    /// Reads content as written to the store.
+    pub fn parse(content_type: ContentType, raw: String) -> Result<Self, String> {
+        match content_type {
+            ContentType::Chat => chat::from_text(&raw)
+                .map(Content::Chat)
+                .map_err(|e| format!("Invalid chat content, {e}")),
+            _ => Ok(Content::Text(raw)),
+        }
+    }
+    /// The text written to the store.
+    pub fn to_raw(&self) -> String {
+        match self {
+            Content::Text(s) => s.clone(),
+            Content::Chat(messages) => serde_json::to_string_pretty(messages).unwrap(),
+        }
+    }
+    /// The template texts: the content itself or every message body.
+    pub fn parts(&self) -> Vec<&str> {
+        match self {
+            Content::Text(s) => vec![s.as_str()],
+            Content::Chat(messages) => messages.iter().map(|m| m.content.as_str()).collect(),
+        }
+    }
+    /// Replaces the texts returned by [`Content::parts`], in the same order.
+    pub fn with_parts(self, parts: Vec<String>) -> Self {
+        match self {
+            Content::Text(_) => Content::Text(parts.into_iter().next().unwrap_or_default()),
+            Content::Chat(messages) => Content::Chat(
+                messages
+                    .into_iter()
+                    .zip(parts)
+                    .map(|(m, content)| Message { content, ..m })
+                    .collect(),
+            ),
+        }
+    }
+}
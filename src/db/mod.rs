@@ -3,6 +3,12 @@
 pub mod prelude;
 
 pub mod organizations;
+pub mod prompt_commits;
+pub mod prompt_includes;
+pub mod prompt_labels;
+pub mod prompt_permissions;
+pub mod prompt_promotions;
+pub mod prompt_versions;
 pub mod prompts;
 pub mod user_organizations;
 pub mod users;
@@ -12,6 +12,7 @@ pub struct Model {
     pub admin_id: i64,
     #[sea_orm(column_type = "Text", nullable)]
     pub description: Option<String>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created_at: DateTimeUtc,
     pub updated_at: Option<DateTimeUtc>,
 }
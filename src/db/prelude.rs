@@ -1,6 +1,12 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
 pub use super::organizations::Entity as Organizations;
+pub use super::prompt_commits::Entity as PromptCommits;
+pub use super::prompt_includes::Entity as PromptIncludes;
+pub use super::prompt_labels::Entity as PromptLabels;
+pub use super::prompt_permissions::Entity as PromptPermissions;
+pub use super::prompt_promotions::Entity as PromptPromotions;
+pub use super::prompt_versions::Entity as PromptVersions;
 pub use super::prompts::Entity as Prompts;
 pub use super::user_organizations::Entity as UserOrganizations;
 pub use super::users::Entity as Users;
@@ -9,11 +9,19 @@ pub struct Model {
     pub id: u64,
     pub latest_version: Option<String>,
     pub latest_commit: Option<String>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created_at: DateTimeUtc,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub updated_at: DateTimeUtc,
     pub user_id: Option<i64>,
     pub file_key: String,
     pub org_id: Option<i64>,
+    #[sea_orm(default_value = "")]
+    pub name: String,
+    #[sea_orm(default_value = "simple")]
+    pub template_engine: String,
+    #[sea_orm(default_value = "plain")]
+    pub content_type: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -12,9 +12,13 @@ pub struct Model {
     #[sea_orm(unique)]
     pub email: String,
     pub password_hash: String,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created_at: DateTimeUtc,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub updated_at: DateTimeUtc,
+    #[sea_orm(default_value = "user")]
     pub role: String,
+    #[sea_orm(default_value = 1)]
     pub valid: i8,
 }
 
@@ -0,0 +1,696 @@
+//! Line diffs of commit contents, as `+/-` text or as a structure clients can
+//! render, with word or char highlights inside changed lines, and application
+//! of unified diffs.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, ChangeTag, DiffOp, TextDiff, capture_diff_slices, group_diff_ops};
+
+/// What is highlighted inside a changed line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    /// whole lines only
+    #[default]
+    Line,
+    Word,
+    Char,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DiffOptions {
+    pub granularity: Granularity,
+    /// split into hunks with this many lines of context, the whole text when absent
+    pub context: Option<usize>,
+    pub ignore_whitespace: bool,
+    pub ignore_case: bool,
+    pub format: DiffFormat,
+}
+
+impl DiffOptions {
+    /// A text diff with `context` is a patch, which has to carry whole lines
+    /// as they are stored.
+    pub fn check(&self) -> Result<(), String> {
+        let exact =
+            self.granularity == Granularity::Line && !self.ignore_whitespace && !self.ignore_case;
+        if self.format == DiffFormat::Text && self.context.is_some() && !exact {
+            return Err(
+                "context can not be combined with word or char granularity or ignore options"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    pub op: LineOp,
+    /// line numbers from 1, absent on the side the line does not exist
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<usize>,
+    pub content: String,
+    /// byte ranges of `content` that changed, for word and char granularity
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// A run of changes with its context. Starts follow unified diffs: from 1, or
+/// the line before for an empty range.
+#[derive(Debug, Serialize)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DiffStats {
+    pub added: usize,
+    pub removed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffReport {
+    pub stats: DiffStats,
+    pub hunks: Vec<Hunk>,
+}
+
+/// The key lines are compared by.
+fn normalize(line: &str, opts: &DiffOptions) -> String {
+    let line: String = if opts.ignore_whitespace {
+        line.chars().filter(|c| !c.is_whitespace()).collect()
+    } else {
+        line.to_string()
+    };
+    if opts.ignore_case {
+        line.to_lowercase()
+    } else {
+        line
+    }
+}
+
+fn hunk_start(range: &Range<usize>) -> usize {
+    if range.is_empty() {
+        range.start
+    } else {
+        range.start + 1
+    }
+}
+
+fn push_span(spans: &mut Vec<Range<usize>>, span: Range<usize>) {
+    match spans.last_mut() {
+        Some(last) if last.end == span.start => last.end = span.end,
+        _ => spans.push(span),
+    }
+}
+
+/// Changed byte ranges of `old` and `new`.
+fn highlights(
+    old: &str,
+    new: &str,
+    granularity: Granularity,
+) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let diff = match granularity {
+        Granularity::Line => return (Vec::new(), Vec::new()),
+        Granularity::Word => TextDiff::from_words(old, new),
+        Granularity::Char => TextDiff::from_chars(old, new),
+    };
+    let (mut old_pos, mut new_pos) = (0, 0);
+    let (mut old_spans, mut new_spans) = (Vec::new(), Vec::new());
+    for change in diff.iter_all_changes() {
+        let len = change.value().len();
+        match change.tag() {
+            ChangeTag::Equal => {
+                old_pos += len;
+                new_pos += len;
+            }
+            ChangeTag::Delete => {
+                push_span(&mut old_spans, old_pos..old_pos + len);
+                old_pos += len;
+            }
+            ChangeTag::Insert => {
+                push_span(&mut new_spans, new_pos..new_pos + len);
+                new_pos += len;
+            }
+        }
+    }
+    (old_spans, new_spans)
+}
+
+fn line(op: LineOp, old_line: Option<usize>, new_line: Option<usize>, content: &str) -> DiffLine {
+    DiffLine {
+        op,
+        old_line: old_line.map(|l| l + 1),
+        new_line: new_line.map(|l| l + 1),
+        content: content.strip_suffix('\n').unwrap_or(content).to_string(),
+        highlights: Vec::new(),
+    }
+}
+
+/// Diffs `left` and `right` line by line.
+pub fn report(left: &str, right: &str, opts: &DiffOptions) -> DiffReport {
+    let old: Vec<&str> = left.split_inclusive('\n').collect();
+    let new: Vec<&str> = right.split_inclusive('\n').collect();
+    let keys =
+        |lines: &[&str]| -> Vec<String> { lines.iter().map(|l| normalize(l, opts)).collect() };
+    let ops = capture_diff_slices(Algorithm::Myers, &keys(&old), &keys(&new));
+
+    let mut stats = DiffStats::default();
+    for op in ops.iter().filter(|op| !matches!(op, DiffOp::Equal { .. })) {
+        stats.removed += op.old_range().len();
+        stats.added += op.new_range().len();
+    }
+    let groups = match opts.context {
+        Some(context) => group_diff_ops(ops, context),
+        None if ops.is_empty() => Vec::new(),
+        None => vec![ops],
+    };
+
+    let mut hunks = Vec::new();
+    for group in groups {
+        let (first, last) = (&group[0], &group[group.len() - 1]);
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let mut lines = Vec::new();
+        for op in &group {
+            let (o, n) = (op.old_range(), op.new_range());
+            if let DiffOp::Equal { .. } = op {
+                for (i, j) in o.zip(n) {
+                    lines.push(line(LineOp::Equal, Some(i), Some(j), new[j]));
+                }
+                continue;
+            }
+            let mut deleted: Vec<DiffLine> = o
+                .map(|i| line(LineOp::Delete, Some(i), None, old[i]))
+                .collect();
+            let mut inserted: Vec<DiffLine> = n
+                .map(|j| line(LineOp::Insert, None, Some(j), new[j]))
+                .collect();
+            // replaced lines are paired up in order for inline highlights
+            for (d, i) in deleted.iter_mut().zip(inserted.iter_mut()) {
+                (d.highlights, i.highlights) = highlights(&d.content, &i.content, opts.granularity);
+            }
+            lines.append(&mut deleted);
+            lines.append(&mut inserted);
+        }
+        hunks.push(Hunk {
+            old_start: hunk_start(&old_range),
+            old_lines: old_range.len(),
+            new_start: hunk_start(&new_range),
+            new_lines: new_range.len(),
+            lines,
+        });
+    }
+    DiffReport { stats, hunks }
+}
+
+/// Writes the diff of `left` and `right` with every line prefixed with `-`,
+/// `+` or a space. When `context` is set hunks get `@@ -a,b +c,d @@` headers
+/// and a missing final newline is marked, so the text is a unified diff.
+/// Highlights are marked `[-removed-]` and `{+added+}`.
+pub fn write_text(res: &mut String, left: &str, right: &str, opts: &DiffOptions) {
+    // the number of the last line when it has no trailing newline
+    let open_end = |text: &str| {
+        (!text.is_empty() && !text.ends_with('\n')).then(|| text.split_inclusive('\n').count())
+    };
+    let (old_end, new_end) = (open_end(left), open_end(right));
+    for hunk in report(left, right, opts).hunks {
+        if opts.context.is_some() {
+            res.push_str(&format!(
+                "@@ -{},{} +{},{} @@\n",
+                hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines
+            ));
+        }
+        for line in hunk.lines {
+            let (sign, open, close) = match line.op {
+                LineOp::Equal => (' ', "", ""),
+                LineOp::Delete => ('-', "[-", "-]"),
+                LineOp::Insert => ('+', "{+", "+}"),
+            };
+            res.push(sign);
+            let mut pos = 0;
+            for span in &line.highlights {
+                res.push_str(&line.content[pos..span.start]);
+                res.push_str(open);
+                res.push_str(&line.content[span.clone()]);
+                res.push_str(close);
+                pos = span.end;
+            }
+            res.push_str(&line.content[pos..]);
+            res.push('\n');
+            let at_end = match line.op {
+                LineOp::Insert => line.new_line == new_end,
+                _ => line.old_line == old_end,
+            };
+            if opts.context.is_some() && at_end {
+                res.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+}
+
+/// A hunk of a unified diff: `@@ -old_start,old_lines +new_start,new_lines @@`.
+#[derive(Debug)]
+pub struct PatchHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    /// body lines without their trailing newline, each starting with ` `, `-` or `+`
+    pub lines: Vec<String>,
+    /// the last line of the new side has no trailing newline
+    pub no_newline: bool,
+}
+
+/// A hunk that does not apply to the base.
+#[derive(Debug, Serialize)]
+pub struct PatchRejection {
+    /// hunk number in the patch, from 1
+    pub hunk: usize,
+    /// the header of the hunk
+    pub header: String,
+    pub message: String,
+}
+
+/// `a` or `a,b` of a hunk header, the count defaulting to 1.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+fn parse_header(line: &str) -> Option<(usize, usize, usize, usize)> {
+    let rest = line.strip_prefix("@@ -")?;
+    let (ranges, _) = rest.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let (old_start, old_lines) = parse_range(old)?;
+    let (new_start, new_lines) = parse_range(new)?;
+    Some((old_start, old_lines, new_start, new_lines))
+}
+
+/// Parses the hunks of a unified diff. Lines before the first hunk, such as
+/// `---`/`+++` file headers, are skipped.
+pub fn parse_patch(patch: &str) -> Result<Vec<PatchHunk>, String> {
+    let mut hunks: Vec<PatchHunk> = Vec::new();
+    let mut lines = patch.lines().peekable();
+    while let Some(line) = lines.next() {
+        if !line.starts_with("@@") {
+            if hunks.is_empty() || line.trim().is_empty() {
+                continue;
+            }
+            return Err(format!(
+                "Unexpected line after hunk {}: {line:?}",
+                hunks.len()
+            ));
+        }
+        let (old_start, old_lines, new_start, new_lines) =
+            parse_header(line).ok_or_else(|| format!("Invalid hunk header {line:?}"))?;
+        let mut hunk = PatchHunk {
+            old_start,
+            old_lines,
+            new_start,
+            new_lines,
+            lines: Vec::new(),
+            no_newline: false,
+        };
+        let (mut old_seen, mut new_seen) = (0, 0);
+        while old_seen < old_lines || new_seen < new_lines {
+            let Some(body) = lines.next() else {
+                return Err(format!("Hunk {line:?} ends early"));
+            };
+            // editors strip the space of empty context lines
+            let body = if body.is_empty() { " " } else { body };
+            match body.as_bytes()[0] {
+                b' ' => {
+                    old_seen += 1;
+                    new_seen += 1;
+                }
+                b'-' => old_seen += 1,
+                b'+' => new_seen += 1,
+                b'\\' => continue,
+                _ => return Err(format!("Invalid line in hunk {line:?}: {body:?}")),
+            }
+            hunk.lines.push(body.to_string());
+        }
+        if old_seen > old_lines || new_seen > new_lines {
+            return Err(format!("Hunk {line:?} has more lines than its header"));
+        }
+        // `\ No newline at end of file` after the last line
+        while let Some(marker) = lines.next_if(|l| l.starts_with('\\')) {
+            let last = hunk.lines.last().map(|l| l.as_bytes()[0]);
+            if marker.starts_with("\\ ") && last != Some(b'-') {
+                hunk.no_newline = true;
+            }
+        }
+        hunks.push(hunk);
+    }
+    if hunks.is_empty() {
+        return Err("Patch has no hunks".to_string());
+    }
+    Ok(hunks)
+}
+
+/// Applies `hunks` to `base`. Every hunk must match the base exactly at its
+/// position; the ones that do not are all reported.
+pub fn apply_patch(base: &str, hunks: &[PatchHunk]) -> Result<String, Vec<PatchRejection>> {
+    let old: Vec<&str> = base.split_inclusive('\n').collect();
+    let mut res = String::new();
+    let mut rejections = Vec::new();
+    let mut pos = 0;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let reject = |message: String| PatchRejection {
+            hunk: i + 1,
+            header: format!(
+                "@@ -{},{} +{},{} @@",
+                hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines
+            ),
+            message,
+        };
+        // an empty old range starts after the line it names
+        let start = if hunk.old_lines == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        if start < pos {
+            rejections.push(reject("Hunk overlaps the previous one".to_string()));
+            continue;
+        }
+        if start + hunk.old_lines > old.len() {
+            rejections.push(reject(format!(
+                "Hunk ends past the end of the base, which has {} lines",
+                old.len()
+            )));
+            continue;
+        }
+        let expected = hunk.lines.iter().filter(|l| !l.starts_with('+'));
+        let mismatch = expected.zip(start..).find(|(l, idx)| {
+            let found = old[*idx].strip_suffix('\n').unwrap_or(old[*idx]);
+            &l[1..] != found
+        });
+        if let Some((l, idx)) = mismatch {
+            rejections.push(reject(format!(
+                "Line {} is {:?}, expected {:?}",
+                idx + 1,
+                old[idx].strip_suffix('\n').unwrap_or(old[idx]),
+                &l[1..]
+            )));
+            continue;
+        }
+
+        res.push_str(&old[pos..start].concat());
+        let last_kept = hunk.lines.iter().rposition(|l| !l.starts_with('-'));
+        let mut idx = start;
+        for (n, l) in hunk.lines.iter().enumerate() {
+            match l.as_bytes()[0] {
+                b'+' => {
+                    res.push_str(&l[1..]);
+                    if !(hunk.no_newline && Some(n) == last_kept) {
+                        res.push('\n');
+                    }
+                }
+                // context keeps the base line as is
+                b' ' => {
+                    res.push_str(old[idx]);
+                    idx += 1;
+                }
+                _ => idx += 1,
+            }
+        }
+        pos = idx;
+    }
+    res.push_str(&old[pos.min(old.len())..].concat());
+    if rejections.is_empty() {
+        Ok(res)
+    } else {
+        Err(rejections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(base: &str, patch: &str) -> Result<String, Vec<PatchRejection>> {
+        apply_patch(base, &parse_patch(patch).unwrap())
+    }
+
+    fn messages(res: Result<String, Vec<PatchRejection>>) -> Vec<(usize, String)> {
+        res.unwrap_err()
+            .into_iter()
+            .map(|r| (r.hunk, r.message))
+            .collect()
+    }
+
+    /// The diff with `context` is a patch turning `left` into `right`.
+    fn round_trip(left: &str, right: &str) {
+        let mut patch = String::new();
+        let opts = DiffOptions {
+            context: Some(1),
+            ..Default::default()
+        };
+        write_text(&mut patch, left, right, &opts);
+        assert_eq!(apply(left, &patch).unwrap(), right, "{patch}");
+    }
+
+    #[test]
+    fn headers_before_the_first_hunk_are_skipped() {
+        let patch = "--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b\n+B\n";
+        assert_eq!(apply("a\nb\nc\n", patch).unwrap(), "a\nB\nc\n");
+    }
+
+    #[test]
+    fn counts_default_to_one() {
+        let hunks = parse_patch("@@ -2 +2 @@\n-b\n+B\n").unwrap();
+        assert_eq!(
+            (hunks[0].old_start, hunks[0].old_lines, hunks[0].new_lines),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn malformed_patches_are_errors() {
+        assert!(parse_patch("no hunks here\n").is_err());
+        assert!(parse_patch("@@ -1,x +1 @@\n").is_err());
+        assert!(parse_patch("@@ -1,2 +1,2 @@\n a\n").is_err());
+        assert!(parse_patch("@@ -1 +1 @@\n-a\n+b\n+c\n").is_err());
+        assert!(parse_patch("@@ -1 +1 @@\n-a\n+b\nstray\n").is_err());
+    }
+
+    #[test]
+    fn insert_into_empty_content() {
+        let patch = "@@ -0,0 +1,2 @@\n+a\n+b\n";
+        assert_eq!(apply("", patch).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn no_newline_on_the_new_side() {
+        let patch = "@@ -1,2 +1,2 @@\n a\n-b\n+c\n\\ No newline at end of file\n";
+        assert_eq!(apply("a\nb\n", patch).unwrap(), "a\nc");
+    }
+
+    #[test]
+    fn no_newline_on_the_old_side() {
+        let patch = "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n";
+        assert_eq!(apply("a\nb", patch).unwrap(), "a\nc\n");
+    }
+
+    #[test]
+    fn no_newline_on_both_sides() {
+        let patch = "@@ -1,2 +1,2 @@\n-a\n+A\n b\n\\ No newline at end of file\n";
+        assert_eq!(apply("a\nb", patch).unwrap(), "A\nb");
+    }
+
+    #[test]
+    fn hunks_apply_in_order() {
+        let patch = "@@ -1 +1 @@\n-a\n+A\n@@ -3,0 +4 @@\n+x\n@@ -5 +6 @@\n-e\n+E\n";
+        assert_eq!(
+            apply("a\nb\nc\nd\ne\n", patch).unwrap(),
+            "A\nb\nc\nx\nd\nE\n"
+        );
+    }
+
+    #[test]
+    fn mismatching_hunks_are_rejected() {
+        let patch = "@@ -1 +1 @@\n-a\n+A\n@@ -2,2 +2,2 @@\n x\n-c\n+C\n";
+        assert_eq!(
+            messages(apply("a\nb\nc\n", patch)),
+            vec![(2, r#"Line 2 is "b", expected "x""#.to_string())]
+        );
+    }
+
+    #[test]
+    fn overlapping_hunks_are_rejected() {
+        let patch = "@@ -1,2 +1,2 @@\n a\n-b\n+B\n@@ -2 +2 @@\n-b\n+X\n";
+        assert_eq!(
+            messages(apply("a\nb\nc\n", patch)),
+            vec![(2, "Hunk overlaps the previous one".to_string())]
+        );
+    }
+
+    #[test]
+    fn hunks_past_the_end_are_rejected() {
+        let rejections = apply("a\n", "@@ -2,1 +2,1 @@\n-b\n+B\n").unwrap_err();
+        assert_eq!(rejections[0].header, "@@ -2,1 +2,1 @@");
+        assert_eq!(
+            rejections[0].message,
+            "Hunk ends past the end of the base, which has 1 lines"
+        );
+    }
+
+    #[test]
+    fn every_failing_hunk_is_reported() {
+        let patch = "@@ -1 +1 @@\n-x\n+A\n@@ -3 +3 @@\n-y\n+C\n";
+        let hunks: Vec<usize> = messages(apply("a\nb\nc\n", patch))
+            .into_iter()
+            .map(|(hunk, _)| hunk)
+            .collect();
+        assert_eq!(hunks, vec![1, 2]);
+    }
+
+    #[test]
+    fn text_diffs_with_context_round_trip() {
+        round_trip("a\nb\nc\nd\ne\nf\ng\n", "a\nB\nc\nd\ne\nF\ng\nh\n");
+        round_trip("", "a\nb\n");
+        round_trip("a\nb\n", "");
+        round_trip("a\nb", "a\nc");
+        round_trip("a\nb", "a\nb\n");
+        round_trip("a\nb\n", "a\nb");
+        round_trip("x\na\nb", "y\na\nb");
+    }
+
+    #[test]
+    fn patches_refuse_inexact_options() {
+        let patch = |opts: DiffOptions| DiffOptions {
+            context: Some(1),
+            ..opts
+        };
+        assert!(patch(DiffOptions::default()).check().is_ok());
+        for opts in [
+            DiffOptions {
+                granularity: Granularity::Word,
+                ..Default::default()
+            },
+            DiffOptions {
+                ignore_whitespace: true,
+                ..Default::default()
+            },
+            DiffOptions {
+                ignore_case: true,
+                ..Default::default()
+            },
+        ] {
+            assert!(opts.check().is_ok());
+            assert!(patch(opts.clone()).check().is_err());
+            let json = DiffOptions {
+                format: DiffFormat::Json,
+                ..patch(opts)
+            };
+            assert!(json.check().is_ok());
+        }
+    }
+
+    fn text(left: &str, right: &str, opts: DiffOptions) -> String {
+        let mut res = String::new();
+        write_text(&mut res, left, right, &opts);
+        res
+    }
+
+    #[test]
+    fn word_and_char_granularity_mark_changes_inside_lines() {
+        let word = DiffOptions {
+            granularity: Granularity::Word,
+            ..Default::default()
+        };
+        assert_eq!(
+            text("say hello world\n", "say hi world\n", word),
+            "-say [-hello-] world\n+say {+hi+} world\n"
+        );
+        let char = DiffOptions {
+            granularity: Granularity::Char,
+            ..Default::default()
+        };
+        assert_eq!(text("colour\n", "color\n", char), "-colo[-u-]r\n+color\n");
+        assert_eq!(
+            text("a\nb\n", "a\nc\n", DiffOptions::default()),
+            " a\n-b\n+c\n"
+        );
+    }
+
+    #[test]
+    fn ignored_differences_count_as_equal() {
+        let ws = DiffOptions {
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        let spaces = report("a  b\nc\n", "a b\nc \n", &ws);
+        assert_eq!((spaces.stats.added, spaces.stats.removed), (0, 0));
+        let case = DiffOptions {
+            ignore_case: true,
+            ..Default::default()
+        };
+        let cased = report("Hello\nWorld\n", "hello\nthere\n", &case);
+        assert_eq!((cased.stats.added, cased.stats.removed), (1, 1));
+        assert_eq!(cased.hunks[0].lines[0].op, LineOp::Equal);
+    }
+
+    #[test]
+    fn json_report_numbers_lines_and_highlights() {
+        let opts = DiffOptions {
+            granularity: Granularity::Word,
+            context: Some(1),
+            ..Default::default()
+        };
+        let res = report("a\nb\nc\nd\ne\nf\n", "a\nb\nc\nd\ne\nf x\n", &opts);
+        assert_eq!(res.hunks.len(), 1);
+        let hunk = &res.hunks[0];
+        assert_eq!(
+            (
+                hunk.old_start,
+                hunk.old_lines,
+                hunk.new_start,
+                hunk.new_lines
+            ),
+            (5, 2, 5, 2)
+        );
+        let lines: Vec<_> = hunk
+            .lines
+            .iter()
+            .map(|l| (l.op, l.old_line, l.new_line))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (LineOp::Equal, Some(5), Some(5)),
+                (LineOp::Delete, Some(6), None),
+                (LineOp::Insert, None, Some(6)),
+            ]
+        );
+        assert_eq!(hunk.lines[2].highlights, vec![1..3]);
+        assert_eq!((res.stats.added, res.stats.removed), (1, 1));
+    }
+}
@@ -13,7 +13,7 @@
 use anyhow::{Context, Result, anyhow};
 use deadpool_redis::redis::AsyncCommands;
 use deadpool_redis::{self, Pool};
-use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbBackend, Schema, ConnectionTrait, Statement};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbBackend, ConnectionTrait, Statement};
 use tokio::time::Duration;
 use tracing::*;
 
@@ -60,7 +60,103 @@ CREATE TABLE IF NOT EXISTS prompts (
   updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
   user_id BIGINT,
   file_key VARCHAR(100) NOT NULL,
-  org_id BIGINT
+  org_id BIGINT,
+  name VARCHAR(255) NOT NULL DEFAULT '',
+  template_engine VARCHAR(16) NOT NULL DEFAULT 'simple',
+  content_type VARCHAR(16) NOT NULL DEFAULT 'plain'
+)
+"#;
+
+    // prompt_versions
+    let versions_sql = r#"
+CREATE TABLE IF NOT EXISTS prompt_versions (
+  id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+  prompt_id BIGINT UNSIGNED NOT NULL,
+  version VARCHAR(32) NOT NULL,
+  base_commit VARCHAR(64) NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  UNIQUE KEY uk_prompt_version (prompt_id, version)
+)
+"#;
+
+    // prompt_commits
+    let commits_sql = r#"
+CREATE TABLE IF NOT EXISTS prompt_commits (
+  id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+  prompt_id BIGINT UNSIGNED NOT NULL,
+  version VARCHAR(32) NOT NULL,
+  commit_id VARCHAR(64) NOT NULL UNIQUE,
+  author VARCHAR(255) NOT NULL,
+  desp TEXT NOT NULL,
+  content_type VARCHAR(16) NOT NULL DEFAULT 'plain',
+  variables JSON NULL,
+  model_config JSON NULL,
+  output_schema JSON NULL,
+  parents JSON NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX idx_prompt_version (prompt_id, version)
+)
+"#;
+
+    // prompt_includes, one row per `{{> ref }}` directive of a commit
+    let includes_sql = r#"
+CREATE TABLE IF NOT EXISTS prompt_includes (
+  id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+  prompt_id BIGINT UNSIGNED NOT NULL,
+  version VARCHAR(32) NOT NULL,
+  commit_id VARCHAR(64) NOT NULL,
+  included_id BIGINT UNSIGNED NOT NULL,
+  reference VARCHAR(160) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX idx_included (included_id),
+  INDEX idx_prompt (prompt_id)
+)
+"#;
+
+    // prompt_labels, the `latest` label lives in prompts.latest_*
+    let labels_sql = r#"
+CREATE TABLE IF NOT EXISTS prompt_labels (
+  id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+  prompt_id BIGINT UNSIGNED NOT NULL,
+  name VARCHAR(64) NOT NULL,
+  version VARCHAR(32) NOT NULL,
+  commit_id VARCHAR(64) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  UNIQUE KEY uk_prompt_label (prompt_id, name)
+)
+"#;
+
+    // prompt_promotions, one row per move of a label, new version/commit are
+    // NULL when the label was deleted
+    let promotions_sql = r#"
+CREATE TABLE IF NOT EXISTS prompt_promotions (
+  id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+  prompt_id BIGINT UNSIGNED NOT NULL,
+  label VARCHAR(64) NOT NULL,
+  prev_version VARCHAR(32),
+  prev_commit VARCHAR(64),
+  version VARCHAR(32),
+  commit_id VARCHAR(64),
+  actor VARCHAR(255) NOT NULL,
+  reason TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX idx_prompt_label_time (prompt_id, label, created_at)
+)
+"#;
+
+    // prompt_permissions, subject_type is `user` or `org`
+    let permissions_sql = r#"
+CREATE TABLE IF NOT EXISTS prompt_permissions (
+  id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+  prompt_id BIGINT UNSIGNED NOT NULL,
+  subject_type VARCHAR(8) NOT NULL,
+  subject_id BIGINT NOT NULL,
+  role VARCHAR(16) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE KEY uk_prompt_subject (prompt_id, subject_type, subject_id),
+  INDEX idx_subject (subject_type, subject_id)
 )
 "#;
 
@@ -86,10 +182,80 @@ CREATE TABLE IF NOT EXISTS user_organizations (
 )
 "#;
 
-    for sql in [users_sql, prompts_sql, orgs_sql, map_sql] {
+    for sql in [
+        users_sql,
+        prompts_sql,
+        versions_sql,
+        commits_sql,
+        includes_sql,
+        labels_sql,
+        promotions_sql,
+        permissions_sql,
+        orgs_sql,
+        map_sql,
+    ] {
         conn.execute(Statement::from_string(backend, sql.to_string())).await?;
     }
 
+    // columns added after the first release
+    ensure_column(conn, "prompts", "name", "VARCHAR(255) NOT NULL DEFAULT ''").await?;
+    ensure_column(
+        conn,
+        "prompts",
+        "template_engine",
+        "VARCHAR(16) NOT NULL DEFAULT 'simple'",
+    )
+    .await?;
+    ensure_column(
+        conn,
+        "prompts",
+        "content_type",
+        "VARCHAR(16) NOT NULL DEFAULT 'plain'",
+    )
+    .await?;
+    ensure_column(conn, "prompt_commits", "variables", "JSON NULL").await?;
+    ensure_column(conn, "prompt_commits", "model_config", "JSON NULL").await?;
+    ensure_column(conn, "prompt_commits", "output_schema", "JSON NULL").await?;
+    ensure_column(conn, "prompt_commits", "parents", "JSON NULL").await?;
+    ensure_column(conn, "prompt_versions", "base_commit", "VARCHAR(64) NULL").await?;
+    ensure_column(
+        conn,
+        "prompt_commits",
+        "content_type",
+        "VARCHAR(16) NOT NULL DEFAULT 'plain'",
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn ensure_column(
+    conn: &DatabaseConnection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let backend = conn.get_database_backend();
+    let exists = conn
+        .query_one(Statement::from_sql_and_values(
+            backend,
+            "SELECT COUNT(*) AS cnt FROM information_schema.COLUMNS \
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND COLUMN_NAME = ?",
+            [table.into(), column.into()],
+        ))
+        .await?
+        .map(|row| row.try_get::<i64>("", "cnt"))
+        .transpose()?
+        .unwrap_or(0)
+        > 0;
+    if !exists {
+        info!("Adding column {table}.{column}");
+        conn.execute(Statement::from_string(
+            backend,
+            format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+        ))
+        .await?;
+    }
     Ok(())
 }
 
@@ -108,6 +274,13 @@ pub async fn get_cache(key: &str, conn: &mut deadpool_redis::Connection) -> Resu
     Ok(value)
 }
 
+pub async fn del_cache(keys: &[String], conn: &mut deadpool_redis::Connection) -> Result<()> {
+    if !keys.is_empty() {
+        conn.del::<_, ()>(keys).await?;
+    }
+    Ok(())
+}
+
 pub async fn set_cache(
     key: &str,
     value: &str,
@@ -1,7 +1,15 @@
+mod content;
 mod db;
+mod diff;
 mod init;
 mod logger;
+mod merge;
+mod migrate;
+mod model_config;
+mod output_schema;
 mod routes;
+mod store;
+mod template;
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -0,0 +1,198 @@
+//! Line-based three-way merge of commit contents.
+
+use std::ops::Range;
+
+use serde::Serialize;
+use similar::{DiffOp, TextDiff};
+
+/// A region both sides changed differently.
+#[derive(Debug, Serialize)]
+pub struct MergeConflict {
+    /// first line of the region in the common ancestor, from 1
+    pub line: usize,
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+/// Lines `base` of the ancestor replaced by lines `lines` of one side.
+#[derive(Debug)]
+struct Change {
+    side: Side,
+    base: Range<usize>,
+    lines: Range<usize>,
+}
+
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Consecutive non-equal ops of one side as a single change.
+fn changes(side: Side, base: &[&str], other: &[&str]) -> Vec<Change> {
+    let diff = TextDiff::from_slices(base, other);
+    let mut res: Vec<Change> = Vec::new();
+    for op in diff.ops() {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let (b, l) = (op.old_range(), op.new_range());
+        match res.last_mut() {
+            Some(last) if last.base.end == b.start && last.lines.end == l.start => {
+                last.base.end = b.end;
+                last.lines.end = l.end;
+            }
+            _ => res.push(Change {
+                side,
+                base: b,
+                lines: l,
+            }),
+        }
+    }
+    res
+}
+
+/// What one side made of `base[range]`, given its changes inside the range.
+fn side_text(base: &[&str], side: &[&str], range: &Range<usize>, changes: &[&Change]) -> String {
+    let mut res = String::new();
+    let mut pos = range.start;
+    for c in changes {
+        res.push_str(&base[pos..c.base.start].concat());
+        res.push_str(&side[c.lines.clone()].concat());
+        pos = c.base.end;
+    }
+    res.push_str(&base[pos..range.end].concat());
+    res
+}
+
+/// Merges the changes `ours` and `theirs` made to `base`. Changes that
+/// overlap or touch are conflicts unless both sides made the same edit.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Result<String, Vec<MergeConflict>> {
+    let (b, o, t) = (lines(base), lines(ours), lines(theirs));
+    let mut all = changes(Side::Ours, &b, &o);
+    all.extend(changes(Side::Theirs, &b, &t));
+    all.sort_by_key(|c| (c.base.start, c.base.end));
+
+    let mut res = String::new();
+    let mut conflicts = Vec::new();
+    let mut pos = 0;
+    let mut i = 0;
+    while i < all.len() {
+        let start = all[i].base.start;
+        let mut end = all[i].base.end;
+        let mut j = i + 1;
+        while j < all.len() && all[j].base.start <= end {
+            end = end.max(all[j].base.end);
+            j += 1;
+        }
+        let group = &all[i..j];
+        let range = start..end;
+        res.push_str(&b[pos..start].concat());
+        let ours_changes: Vec<&Change> = group.iter().filter(|c| c.side == Side::Ours).collect();
+        let theirs_changes: Vec<&Change> =
+            group.iter().filter(|c| c.side == Side::Theirs).collect();
+        let ours_text = side_text(&b, &o, &range, &ours_changes);
+        let theirs_text = side_text(&b, &t, &range, &theirs_changes);
+        if theirs_changes.is_empty() || ours_text == theirs_text {
+            res.push_str(&ours_text);
+        } else if ours_changes.is_empty() {
+            res.push_str(&theirs_text);
+        } else {
+            conflicts.push(MergeConflict {
+                line: start + 1,
+                base: b[range].concat(),
+                ours: ours_text,
+                theirs: theirs_text,
+            });
+        }
+        pos = end;
+        i = j;
+    }
+    res.push_str(&b[pos..].concat());
+    if conflicts.is_empty() {
+        Ok(res)
+    } else {
+        Err(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "a\nb\nc\nd\ne\n";
+
+    #[test]
+    fn changes_apart_merge_cleanly() {
+        let ours = "A\nb\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\n";
+        assert_eq!(merge3(BASE, ours, theirs).unwrap(), "A\nb\nc\nd\nE\n");
+        assert_eq!(merge3(BASE, theirs, ours).unwrap(), "A\nb\nc\nd\nE\n");
+    }
+
+    #[test]
+    fn unchanged_theirs_keeps_ours() {
+        let ours = "a\nc\nd\nx\ne\n";
+        assert_eq!(merge3(BASE, ours, BASE).unwrap(), ours);
+    }
+
+    #[test]
+    fn fast_forward_takes_theirs() {
+        let theirs = "a\nb\nC\nd\ne\nf\n";
+        assert_eq!(merge3(BASE, BASE, theirs).unwrap(), theirs);
+    }
+
+    #[test]
+    fn identical_edits_merge_once() {
+        let both = "a\nB\nc\nd\ne\nf\n";
+        assert_eq!(merge3(BASE, both, both).unwrap(), both);
+    }
+
+    #[test]
+    fn overlapping_edits_conflict() {
+        let res = merge3(BASE, "a\nB\nc\nd\ne\n", "a\nb2\nc\nd\ne\n");
+        let conflicts = res.unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        let c = &conflicts[0];
+        assert_eq!(
+            (c.line, c.base.as_str(), c.ours.as_str(), c.theirs.as_str()),
+            (2, "b\n", "B\n", "b2\n")
+        );
+    }
+
+    #[test]
+    fn adjacent_edits_conflict() {
+        let res = merge3(BASE, "a\nB\nc\nd\ne\n", "a\nb\nC\nd\ne\n");
+        let conflicts = res.unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        let c = &conflicts[0];
+        assert_eq!(
+            (c.line, c.base.as_str(), c.ours.as_str(), c.theirs.as_str()),
+            (2, "b\nc\n", "B\nc\n", "b\nC\n")
+        );
+    }
+
+    #[test]
+    fn insertions_at_the_same_point_conflict() {
+        let res = merge3(BASE, "a\nb\nx\nc\nd\ne\n", "a\nb\ny\nc\nd\ne\n");
+        let conflicts = res.unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        let c = &conflicts[0];
+        assert_eq!(
+            (c.line, c.base.as_str(), c.ours.as_str(), c.theirs.as_str()),
+            (3, "", "x\n", "y\n")
+        );
+    }
+
+    #[test]
+    fn every_conflict_is_reported() {
+        let res = merge3(BASE, "A\nb\nc\nd\nE\n", "a1\nb\nc\nd\ne1\n");
+        let lines: Vec<usize> = res.unwrap_err().iter().map(|c| c.line).collect();
+        assert_eq!(lines, vec![1, 5]);
+    }
+}
@@ -0,0 +1,54 @@
+//! One-shot import of the legacy per-prompt `info.json` files into the
+//! `prompt_versions` / `prompt_commits` tables.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use sea_orm::{DatabaseConnection, EntityTrait, QuerySelect};
+use tracing::{info, warn};
+
+use crate::{
+    db::{
+        prelude::{PromptVersions, Prompts as PromptData},
+        prompt_versions,
+    },
+    routes::{common::Prompts, error::ShelfError},
+    store::PromptStore,
+};
+
+/// Prompts that already own a version row are skipped, so running it twice is harmless.
+pub async fn import_info_json(conn: &DatabaseConnection, store: &dyn PromptStore) -> Result<usize> {
+    let imported: HashSet<u64> = PromptVersions::find()
+        .select_only()
+        .column(prompt_versions::Column::PromptId)
+        .distinct()
+        .into_tuple::<u64>()
+        .all(conn)
+        .await?
+        .into_iter()
+        .collect();
+    let mut count = 0;
+    for prompt in PromptData::find().all(conn).await? {
+        if imported.contains(&prompt.id) {
+            continue;
+        }
+        let legacy = match Prompts::load_legacy(store, &prompt.file_key).await {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("Skip prompt {}, info.json not readable: {e}", prompt.id);
+                continue;
+            }
+        };
+        match legacy.import(conn, prompt.id).await {
+            Ok(()) => {}
+            Err(ShelfError::Validation(e)) => {
+                warn!("Skip prompt {}, {e}", prompt.id);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        info!("Imported info.json of prompt {}", prompt.id);
+        count += 1;
+    }
+    Ok(count)
+}
@@ -0,0 +1,55 @@
+//! Model parameters committed together with a prompt, so a service fetching a
+//! commit gets everything it needs to call the model.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// stop sequences
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// The OpenAI `response_format` object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema { json_schema: Value },
+}
+
+impl ModelConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.model.as_deref().is_some_and(|m| m.trim().is_empty()) {
+            return Err("model must not be empty".to_string());
+        }
+        if let Some(t) = self.temperature
+            && !(0.0..=2.0).contains(&t)
+        {
+            return Err(format!("temperature must be between 0 and 2, got {t}"));
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be positive".to_string());
+        }
+        if self.stop.iter().any(String::is_empty) {
+            return Err("stop sequences must not be empty".to_string());
+        }
+        if let Some(ResponseFormat::JsonSchema { json_schema }) = &self.response_format
+            && !json_schema.is_object()
+        {
+            return Err("response_format.json_schema must be an object".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+//! JSON Schemas that the output of a model must satisfy, for prompts asking for
+//! structured answers.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// A place where an output does not satisfy the schema.
+#[derive(Debug, Serialize)]
+pub struct OutputIssue {
+    /// JSON pointer into the output, empty for the whole document
+    pub path: String,
+    pub message: String,
+}
+
+/// Checks that `schema` is a well-formed JSON Schema.
+pub fn check(schema: &Value) -> Result<(), String> {
+    jsonschema::validator_for(schema)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Parses `output` as JSON and validates it against `schema`.
+pub fn validate(schema: &Value, output: &str) -> Result<Vec<OutputIssue>, String> {
+    let validator = jsonschema::validator_for(schema).map_err(|e| e.to_string())?;
+    let output: Value = match serde_json::from_str(output) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(vec![OutputIssue {
+                path: String::new(),
+                message: format!("Output is not valid JSON: {e}"),
+            }]);
+        }
+    };
+    Ok(validator
+        .iter_errors(&output)
+        .map(|e| OutputIssue {
+            path: e.instance_path().to_string(),
+            message: e.to_string(),
+        })
+        .collect())
+}
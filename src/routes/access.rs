@@ -0,0 +1,417 @@
+use std::{collections::HashMap, fmt, marker::PhantomData, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Query, RawPathParams, Request, State},
+    http::request::Parts,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter,
+    QuerySelect, QueryTrait,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use super::{
+    common::{AppCode, AppResponse, AppState},
+    error::{ShelfError, ShelfResult},
+    middleware::TokenClaims,
+    prompt::evict_prompts,
+};
+use crate::db::{
+    prelude::{Organizations, PromptPermissions, Prompts as PromptData, UserOrganizations, Users},
+    prompt_permissions, prompts, user_organizations, users,
+};
+
+/// Access level on a single prompt, ordered from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptRole {
+    /// read content, history and diffs
+    Viewer,
+    /// create versions and commits
+    Editor,
+    /// move the latest pointer: rollback, revert, commit as latest
+    Maintainer,
+    /// delete the prompt and manage its permissions
+    Owner,
+}
+
+impl PromptRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PromptRole::Viewer => "viewer",
+            PromptRole::Editor => "editor",
+            PromptRole::Maintainer => "maintainer",
+            PromptRole::Owner => "owner",
+        }
+    }
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "viewer" => Some(PromptRole::Viewer),
+            "editor" => Some(PromptRole::Editor),
+            "maintainer" => Some(PromptRole::Maintainer),
+            "owner" => Some(PromptRole::Owner),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PromptRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+const SUBJECT_USER: &str = "user";
+pub const SUBJECT_ORG: &str = "org";
+
+fn member_orgs(user_id: i64) -> sea_orm::sea_query::SelectStatement {
+    UserOrganizations::find()
+        .select_only()
+        .column(user_organizations::Column::OrgId)
+        .filter(user_organizations::Column::UserId.eq(user_id))
+        .into_query()
+}
+
+/// Grants that apply to `user_id`, directly or through one of its organizations.
+fn grants_of(user_id: i64) -> Condition {
+    Condition::any()
+        .add(
+            Condition::all()
+                .add(prompt_permissions::Column::SubjectType.eq(SUBJECT_USER))
+                .add(prompt_permissions::Column::SubjectId.eq(user_id)),
+        )
+        .add(
+            Condition::all()
+                .add(prompt_permissions::Column::SubjectType.eq(SUBJECT_ORG))
+                .add(prompt_permissions::Column::SubjectId.in_subquery(member_orgs(user_id))),
+        )
+}
+
+/// Prompts on which `user_id` holds any role.
+pub fn visible_to(user_id: i64) -> Condition {
+    let granted = PromptPermissions::find()
+        .select_only()
+        .column(prompt_permissions::Column::PromptId)
+        .filter(grants_of(user_id))
+        .into_query();
+    Condition::any()
+        .add(prompts::Column::UserId.eq(user_id))
+        .add(prompts::Column::OrgId.in_subquery(member_orgs(user_id)))
+        .add(prompts::Column::Id.in_subquery(granted))
+}
+
+/// The creator and the admin of the owning organization are owners. A grant
+/// to the user itself sets the role of anyone else, so an organization member
+/// can be held to viewer; otherwise members are maintainers and grants to
+/// their organizations can only raise the role.
+pub async fn role_of(
+    conn: &DatabaseConnection,
+    user_id: i64,
+    prompt: &prompts::Model,
+) -> ShelfResult<Option<PromptRole>> {
+    if prompt.user_id == Some(user_id) {
+        return Ok(Some(PromptRole::Owner));
+    }
+    let mut role = None;
+    if let Some(org_id) = prompt.org_id
+        && UserOrganizations::find_by_id((user_id, org_id))
+            .one(conn)
+            .await?
+            .is_some()
+    {
+        let is_org_admin = Organizations::find_by_id(org_id)
+            .one(conn)
+            .await?
+            .is_some_and(|o| o.admin_id == user_id);
+        if is_org_admin {
+            return Ok(Some(PromptRole::Owner));
+        }
+        role = Some(PromptRole::Maintainer);
+    }
+    let grants = PromptPermissions::find()
+        .filter(prompt_permissions::Column::PromptId.eq(prompt.id))
+        .filter(grants_of(user_id))
+        .all(conn)
+        .await?;
+    if let Some(grant) = grants.iter().find(|g| g.subject_type == SUBJECT_USER) {
+        return Ok(PromptRole::parse(&grant.role));
+    }
+    for grant in grants {
+        role = role.max(PromptRole::parse(&grant.role));
+    }
+    Ok(role)
+}
+
+pub fn role_denied(required: PromptRole, actual: PromptRole) -> ShelfError {
+    ShelfError::Forbidden(format!(
+        "Permission denied, {required} role is required but you are {actual}"
+    ))
+}
+
+/// The one place prompt access is checked. A prompt the user cannot see at
+/// all is reported as missing rather than forbidden.
+pub async fn authorize(
+    conn: &DatabaseConnection,
+    user_id: i64,
+    prompt_id: u64,
+    required: PromptRole,
+) -> ShelfResult<(prompts::Model, PromptRole)> {
+    let not_found = || ShelfError::NotFound("Prompt id not exist!".to_string());
+    let prompt = PromptData::find_by_id(prompt_id)
+        .one(conn)
+        .await?
+        .ok_or_else(not_found)?;
+    match role_of(conn, user_id, &prompt).await? {
+        None => Err(not_found()),
+        Some(actual) if actual < required => Err(role_denied(required, actual)),
+        Some(actual) => Ok((prompt, actual)),
+    }
+}
+
+/// Minimum role a handler needs, used as the parameter of [`AuthorizedPrompt`].
+pub trait Access: Send + Sync + 'static {
+    const ROLE: PromptRole;
+}
+
+pub struct Read;
+pub struct Write;
+pub struct Manage;
+pub struct Own;
+
+impl Access for Read {
+    const ROLE: PromptRole = PromptRole::Viewer;
+}
+impl Access for Write {
+    const ROLE: PromptRole = PromptRole::Editor;
+}
+impl Access for Manage {
+    const ROLE: PromptRole = PromptRole::Maintainer;
+}
+impl Access for Own {
+    const ROLE: PromptRole = PromptRole::Owner;
+}
+
+/// A prompt the caller holds at least `A::ROLE` on. The id is taken from the
+/// `{prompt_id}` path segment, or the `prompt_id` (or `id`) query parameter.
+pub struct AuthorizedPrompt<A: Access> {
+    pub prompt: prompts::Model,
+    pub role: PromptRole,
+    _access: PhantomData<A>,
+}
+
+impl<A: Access> AuthorizedPrompt<A> {
+    async fn resolve(
+        state: &AppState,
+        claims: Option<&TokenClaims>,
+        prompt_id: Option<u64>,
+    ) -> Result<Self, AppResponse<()>> {
+        let claims = claims.ok_or_else(|| {
+            AppResponse::new(
+                AppCode::Unauthorized,
+                "Invalid or missing token".to_string(),
+                None,
+            )
+        })?;
+        let prompt_id =
+            prompt_id.ok_or_else(|| AppResponse::bad_request("prompt_id is required"))?;
+        let (prompt, role) = authorize(&state.sql_conn, claims.id, prompt_id, A::ROLE).await?;
+        Ok(Self {
+            prompt,
+            role,
+            _access: PhantomData,
+        })
+    }
+}
+
+impl<A: Access> FromRequestParts<Arc<AppState>> for AuthorizedPrompt<A> {
+    type Rejection = AppResponse<()>;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let mut prompt_id = None;
+        if let Ok(params) = RawPathParams::from_request_parts(parts, state).await {
+            prompt_id = params
+                .iter()
+                .find(|(k, _)| *k == "prompt_id")
+                .and_then(|(_, v)| v.parse().ok());
+        }
+        if prompt_id.is_none()
+            && let Ok(Query(query)) = Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+        {
+            prompt_id = query
+                .get("prompt_id")
+                .or_else(|| query.get("id"))
+                .and_then(|v| v.parse().ok());
+        }
+        Self::resolve(state, parts.extensions.get::<TokenClaims>(), prompt_id).await
+    }
+}
+
+/// A JSON body together with the prompt named by its `prompt_id` field.
+pub struct AuthorizedJson<A: Access, T> {
+    pub auth: AuthorizedPrompt<A>,
+    pub payload: T,
+}
+
+impl<A: Access, T: DeserializeOwned> FromRequest<Arc<AppState>> for AuthorizedJson<A, T> {
+    type Rejection = AppResponse<()>;
+
+    async fn from_request(req: Request, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let claims = req.extensions().get::<TokenClaims>().cloned();
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| AppResponse::bad_request(format!("Failed to read body: {e}")))?;
+        let value: Value = serde_json::from_slice(&body)
+            .map_err(|e| AppResponse::bad_request(format!("Invalid json body: {e}")))?;
+        let prompt_id = value.get("prompt_id").and_then(Value::as_u64);
+        let auth = AuthorizedPrompt::resolve(state, claims.as_ref(), prompt_id).await?;
+        let payload = serde_json::from_value(value)
+            .map_err(|e| AppResponse::bad_request(format!("Invalid json body: {e}")))?;
+        Ok(Self { auth, payload })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct GrantInfo {
+    user_id: Option<i64>,
+    email: Option<String>,
+    org_id: Option<i64>,
+    role: PromptRole,
+}
+
+pub async fn grant(
+    State(data): State<Arc<AppState>>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Own, GrantInfo>,
+) -> AppResponse<String> {
+    let prompt_id = auth.prompt.id;
+    let (subject_type, subject_id) = if let Some(org_id) = payload.org_id {
+        match Organizations::find_by_id(org_id).one(&data.sql_conn).await {
+            Ok(Some(o)) => (SUBJECT_ORG, o.id),
+            Ok(None) => return AppResponse::not_found("Organization not exist"),
+            Err(e) => return ShelfError::Db(e).into(),
+        }
+    } else {
+        let query = match (payload.user_id, payload.email) {
+            (Some(id), _) => Users::find_by_id(id),
+            (None, Some(email)) => Users::find().filter(users::Column::Email.eq(email)),
+            (None, None) => return AppResponse::bad_request("Missing user_id, email or org_id"),
+        };
+        match query.one(&data.sql_conn).await {
+            Ok(Some(u)) => (SUBJECT_USER, u.id),
+            Ok(None) => return AppResponse::not_found("User not exist"),
+            Err(e) => return ShelfError::Db(e).into(),
+        }
+    };
+    let existing = match PromptPermissions::find()
+        .filter(prompt_permissions::Column::PromptId.eq(prompt_id))
+        .filter(prompt_permissions::Column::SubjectType.eq(subject_type))
+        .filter(prompt_permissions::Column::SubjectId.eq(subject_id))
+        .one(&data.sql_conn)
+        .await
+    {
+        Ok(p) => p,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let res = match existing {
+        Some(p) => PromptPermissions::update(prompt_permissions::ActiveModel {
+            id: Set(p.id),
+            role: Set(payload.role.as_str().to_string()),
+            ..Default::default()
+        })
+        .exec(&data.sql_conn)
+        .await
+        .map(|_| ()),
+        None => PromptPermissions::insert(prompt_permissions::ActiveModel {
+            prompt_id: Set(prompt_id),
+            subject_type: Set(subject_type.to_string()),
+            subject_id: Set(subject_id),
+            role: Set(payload.role.as_str().to_string()),
+            ..Default::default()
+        })
+        .exec(&data.sql_conn)
+        .await
+        .map(|_| ()),
+    };
+    if let Err(e) = res {
+        return ShelfError::Db(e).into();
+    }
+    evict_prompts(&data.redis_pool, &[prompt_id]).await;
+    AppResponse::ok(
+        format!(
+            "Granted {} to {subject_type} {subject_id}",
+            payload.role.as_str()
+        ),
+        None,
+    )
+}
+
+#[derive(Serialize)]
+pub struct PermissionInfo {
+    subject_type: String,
+    subject_id: i64,
+    role: String,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn list_permission(
+    State(data): State<Arc<AppState>>,
+    auth: AuthorizedPrompt<Own>,
+) -> AppResponse<Vec<PermissionInfo>> {
+    let grants = match PromptPermissions::find()
+        .filter(prompt_permissions::Column::PromptId.eq(auth.prompt.id))
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(g) => g,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let res = grants
+        .into_iter()
+        .map(|g| PermissionInfo {
+            subject_type: g.subject_type,
+            subject_id: g.subject_id,
+            role: g.role,
+            created_at: g.created_at,
+        })
+        .collect();
+    AppResponse::ok("Query permissions finished".to_string(), Some(res))
+}
+
+#[derive(Deserialize)]
+pub struct RevokeInfo {
+    user_id: Option<i64>,
+    org_id: Option<i64>,
+}
+
+pub async fn revoke(
+    State(data): State<Arc<AppState>>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Own, RevokeInfo>,
+) -> AppResponse<String> {
+    let (subject_type, subject_id) = match (payload.org_id, payload.user_id) {
+        (Some(org_id), _) => (SUBJECT_ORG, org_id),
+        (None, Some(user_id)) => (SUBJECT_USER, user_id),
+        (None, None) => return AppResponse::bad_request("Missing user_id or org_id"),
+    };
+    match PromptPermissions::delete_many()
+        .filter(prompt_permissions::Column::PromptId.eq(auth.prompt.id))
+        .filter(prompt_permissions::Column::SubjectType.eq(subject_type))
+        .filter(prompt_permissions::Column::SubjectId.eq(subject_id))
+        .exec(&data.sql_conn)
+        .await
+    {
+        Ok(r) if r.rows_affected == 0 => AppResponse::not_found("Permission not exist"),
+        Ok(_) => {
+            evict_prompts(&data.redis_pool, &[auth.prompt.id]).await;
+            AppResponse::ok(
+                format!("Revoked permission of {subject_type} {subject_id}"),
+                None,
+            )
+        }
+        Err(e) => ShelfError::Db(e).into(),
+    }
+}
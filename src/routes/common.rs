@@ -10,36 +10,55 @@
 //                                                                            //
 // ************************************************************************** //
 
-use std::fmt::{self, Write};
 use std::{
-    path::Path,
-    sync::{OnceLock, atomic::AtomicBool},
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, OnceLock, atomic::AtomicBool},
     time::SystemTime,
 };
 
-use anyhow::{Ok, Result, anyhow};
 use axum::{Json, http::StatusCode, response::IntoResponse};
 use chrono::{DateTime, Utc};
 use deadpool_redis::Pool;
-use sea_orm::DatabaseConnection;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait, sea_query::Expr,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use similar::{ChangeTag, TextDiff};
-use tokio::fs;
 use uuid::Uuid;
 
 use super::{
     config::Config,
-    finder::{find_commit, find_config, find_prompt},
+    error::{ShelfError, ShelfResult},
+    label::LabelTarget,
+};
+use crate::{
+    content::{Content, ContentType, chat},
+    db::{
+        prelude::{
+            PromptCommits, PromptIncludes, PromptLabels, PromptPermissions, PromptPromotions,
+            PromptVersions,
+        },
+        prompt_commits, prompt_includes, prompt_labels, prompt_permissions, prompt_promotions,
+        prompt_versions,
+        prompts::{self, Entity as PromptData},
+    },
+    diff::{self, DiffFormat, DiffOptions, DiffReport},
+    model_config::ModelConfig,
+    store::PromptStore,
+    template::schema::Variable,
 };
 
-pub const MAX_CONCURRENT_TASKS: usize = 8;
 pub static START_TIME: OnceLock<SystemTime> = OnceLock::new();
+/// Width of the `version` columns.
+const MAX_VERSION_LEN: usize = 32;
+
 pub struct AppState {
     pub sql_conn: DatabaseConnection,
     pub config: Config,
     pub allow_register: AtomicBool,
     pub redis_pool: Pool,
+    pub store: Arc<dyn PromptStore>,
 }
 
 #[derive(Debug, Serialize, Clone, Copy)]
@@ -50,6 +69,8 @@ pub enum AppCode {
     Unauthorized = 401,
     Forbidden = 403,
     NotFound = 404,
+    Conflict = 409,
+    UnprocessableEntity = 422,
     InternalError = 500,
 }
 
@@ -61,6 +82,8 @@ impl AppCode {
             AppCode::Unauthorized => StatusCode::UNAUTHORIZED,
             AppCode::Forbidden => StatusCode::FORBIDDEN,
             AppCode::NotFound => StatusCode::NOT_FOUND,
+            AppCode::Conflict => StatusCode::CONFLICT,
+            AppCode::UnprocessableEntity => StatusCode::UNPROCESSABLE_ENTITY,
             AppCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -100,6 +123,20 @@ impl<T: Serialize> AppResponse<T> {
             result: None,
         }
     }
+    pub fn forbidden(msg: impl Into<String>) -> Self {
+        Self {
+            status: AppCode::Forbidden,
+            msg: msg.into(),
+            result: None,
+        }
+    }
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self {
+            status: AppCode::NotFound,
+            msg: msg.into(),
+            result: None,
+        }
+    }
     pub fn internal_err(msg: impl Into<String>) -> Self {
         Self {
             status: AppCode::InternalError,
@@ -115,6 +152,19 @@ pub struct PromptCommit {
     pub commit_id: String,
     pub created_at: DateTime<Utc>,
     pub desp: String,
+    #[serde(default)]
+    pub content_type: ContentType,
+    /// declared template variables, absent for commits made without a schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variables: Option<Vec<Variable>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_config: Option<ModelConfig>,
+    /// JSON Schema the model output must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+    /// commits this one derives from, two for a merge
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<String>,
 }
 
 impl PromptCommit {
@@ -124,6 +174,11 @@ impl PromptCommit {
             desp,
             commit_id: Uuid::new_v4().to_string(),
             created_at: Utc::now(),
+            content_type: ContentType::default(),
+            variables: None,
+            model_config: None,
+            output_schema: None,
+            parents: Vec::new(),
         }
     }
 }
@@ -133,14 +188,18 @@ pub struct PromptNode {
     pub version: String,
     pub commits: Vec<PromptCommit>,
     pub updated_at: DateTime<Utc>,
+    /// the commit this version was branched from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_commit: Option<String>,
 }
 
 impl PromptNode {
-    pub fn new(version: String) -> Self {
+    pub fn new(version: String, base_commit: Option<String>) -> Self {
         Self {
             version,
             commits: Vec::new(),
             updated_at: Utc::now(),
+            base_commit,
         }
     }
 }
@@ -167,20 +226,166 @@ impl Prompts {
         self.name.clone()
     }
 
-    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(&path).await?;
-        let data: Self = serde_json::from_str(&content)?;
-        Ok(data)
+    fn from_rows(
+        prompt: &prompts::Model,
+        versions: Vec<prompt_versions::Model>,
+        commits: Vec<prompt_commits::Model>,
+    ) -> Self {
+        let mut nodes: Vec<PromptNode> = versions
+            .into_iter()
+            .map(|v| PromptNode {
+                version: v.version,
+                commits: Vec::new(),
+                updated_at: v.updated_at,
+                base_commit: v.base_commit,
+            })
+            .collect();
+        for c in commits {
+            if let Some(node) = nodes.iter_mut().find(|n| n.version == c.version) {
+                node.commits.push(PromptCommit {
+                    author: c.author,
+                    commit_id: c.commit_id,
+                    created_at: c.created_at,
+                    desp: c.desp,
+                    content_type: ContentType::parse(&c.content_type).unwrap_or_default(),
+                    variables: c.variables.and_then(|v| serde_json::from_value(v).ok()),
+                    model_config: c.model_config.and_then(|v| serde_json::from_value(v).ok()),
+                    output_schema: c.output_schema,
+                    parents: c
+                        .parents
+                        .and_then(|v| serde_json::from_value(v).ok())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+        Self {
+            name: prompt.name.clone(),
+            id: prompt.file_key.clone(),
+            nodes,
+        }
+    }
+    pub async fn load(conn: &DatabaseConnection, prompt: &prompts::Model) -> ShelfResult<Self> {
+        let versions = PromptVersions::find()
+            .filter(prompt_versions::Column::PromptId.eq(prompt.id))
+            .order_by_asc(prompt_versions::Column::Id)
+            .all(conn)
+            .await?;
+        let commits = PromptCommits::find()
+            .filter(prompt_commits::Column::PromptId.eq(prompt.id))
+            .order_by_asc(prompt_commits::Column::Id)
+            .all(conn)
+            .await?;
+        Ok(Self::from_rows(prompt, versions, commits))
+    }
+    /// Loads the version trees of several prompts with two queries, keyed by prompt id.
+    pub async fn load_many(
+        conn: &DatabaseConnection,
+        prompt_list: &[prompts::Model],
+    ) -> ShelfResult<HashMap<u64, Self>> {
+        let ids: Vec<u64> = prompt_list.iter().map(|p| p.id).collect();
+        let mut versions: HashMap<u64, Vec<prompt_versions::Model>> = HashMap::new();
+        for v in PromptVersions::find()
+            .filter(prompt_versions::Column::PromptId.is_in(ids.clone()))
+            .order_by_asc(prompt_versions::Column::Id)
+            .all(conn)
+            .await?
+        {
+            versions.entry(v.prompt_id).or_default().push(v);
+        }
+        let mut commits: HashMap<u64, Vec<prompt_commits::Model>> = HashMap::new();
+        for c in PromptCommits::find()
+            .filter(prompt_commits::Column::PromptId.is_in(ids))
+            .order_by_asc(prompt_commits::Column::Id)
+            .all(conn)
+            .await?
+        {
+            commits.entry(c.prompt_id).or_default().push(c);
+        }
+        Ok(prompt_list
+            .iter()
+            .map(|p| {
+                let prompt = Self::from_rows(
+                    p,
+                    versions.remove(&p.id).unwrap_or_default(),
+                    commits.remove(&p.id).unwrap_or_default(),
+                );
+                (p.id, prompt)
+            })
+            .collect())
     }
-    pub async fn save(&self) -> Result<()> {
-        let path = find_config(&self.id)?;
-        let content = serde_json::to_string_pretty(&self)?;
-        fs::write(path, &content).await?;
+    /// Reads a pre-SQL `info.json`, only used to import old data.
+    pub async fn load_legacy(store: &dyn PromptStore, file_key: &str) -> ShelfResult<Self> {
+        let content = store
+            .read_config(file_key)
+            .await
+            .map_err(ShelfError::Storage)?;
+        serde_json::from_str(&content)
+            .map_err(|e| ShelfError::Validation(format!("Invalid info.json of {file_key}: {e}")))
+    }
+    /// Writes every version and commit of a legacy config into the SQL tables.
+    pub async fn import(&self, conn: &DatabaseConnection, prompt_id: u64) -> ShelfResult<()> {
+        for node in &self.nodes {
+            check_version_len(&node.version)?;
+        }
+        let txn = conn.begin().await?;
+        for node in &self.nodes {
+            PromptVersions::insert(prompt_versions::ActiveModel {
+                prompt_id: Set(prompt_id),
+                version: Set(node.version.clone()),
+                updated_at: Set(node.updated_at),
+                ..Default::default()
+            })
+            .exec(&txn)
+            .await?;
+            for com in &node.commits {
+                insert_commit(&txn, prompt_id, &node.version, com).await?;
+            }
+        }
+        PromptData::update_many()
+            .col_expr(prompts::Column::Name, Expr::value(self.name.clone()))
+            .filter(prompts::Column::Id.eq(prompt_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
         Ok(())
     }
-    pub async fn delete(file_key: &str) -> Result<()> {
-        let path = find_prompt(file_key)?;
-        fs::remove_dir_all(path).await?;
+    pub async fn delete(
+        conn: &DatabaseConnection,
+        store: &dyn PromptStore,
+        prompt_id: u64,
+        file_key: &str,
+    ) -> ShelfResult<()> {
+        let txn = conn.begin().await?;
+        PromptCommits::delete_many()
+            .filter(prompt_commits::Column::PromptId.eq(prompt_id))
+            .exec(&txn)
+            .await?;
+        PromptVersions::delete_many()
+            .filter(prompt_versions::Column::PromptId.eq(prompt_id))
+            .exec(&txn)
+            .await?;
+        PromptLabels::delete_many()
+            .filter(prompt_labels::Column::PromptId.eq(prompt_id))
+            .exec(&txn)
+            .await?;
+        PromptIncludes::delete_many()
+            .filter(prompt_includes::Column::PromptId.eq(prompt_id))
+            .exec(&txn)
+            .await?;
+        PromptPromotions::delete_many()
+            .filter(prompt_promotions::Column::PromptId.eq(prompt_id))
+            .exec(&txn)
+            .await?;
+        PromptPermissions::delete_many()
+            .filter(prompt_permissions::Column::PromptId.eq(prompt_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        // blobs go last so a failed delete leaves the prompt readable
+        store
+            .delete_prompt(file_key)
+            .await
+            .map_err(ShelfError::Storage)?;
         Ok(())
     }
 
@@ -195,88 +400,373 @@ impl Prompts {
             .map(|c| c.commit_id.clone())
             .collect()
     }
-    pub async fn create_version(&mut self, version: &str) -> Result<()> {
+    pub async fn create_version(
+        &mut self,
+        conn: &DatabaseConnection,
+        prompt_id: u64,
+        version: &str,
+        base_commit: Option<String>,
+    ) -> ShelfResult<()> {
+        check_version_len(version)?;
         if self.nodes.iter().any(|n| n.version == version) {
-            return Err(anyhow!("Version {} already exists!", version));
+            return Err(ShelfError::Conflict(format!(
+                "Version {version} already exists!"
+            )));
+        }
+        if let Some(base) = &base_commit
+            && self.locate(base).is_none()
+        {
+            return Err(commit_not_found(base));
         }
-        let node = PromptNode::new(version.to_string());
+        let node = PromptNode::new(version.to_string(), base_commit);
+        PromptVersions::insert(prompt_versions::ActiveModel {
+            prompt_id: Set(prompt_id),
+            version: Set(node.version.clone()),
+            base_commit: Set(node.base_commit.clone()),
+            updated_at: Set(node.updated_at),
+            ..Default::default()
+        })
+        .exec(conn)
+        .await?;
         self.nodes.push(node);
         Ok(())
     }
-    pub async fn commit(&mut self, version: &str, com: PromptCommit, content: &str) -> Result<()> {
-        let save_path = find_commit(&self.id, version, &com.commit_id)?;
-        fs::write(save_path, content).await?;
+    pub async fn commit(
+        &mut self,
+        conn: &DatabaseConnection,
+        store: &dyn PromptStore,
+        prompt_id: u64,
+        version: &str,
+        mut com: PromptCommit,
+        content: &str,
+    ) -> ShelfResult<()> {
+        if !self.nodes.iter().any(|n| n.version == version) {
+            return Err(version_not_found(version));
+        }
+        if com.parents.is_empty() {
+            com.parents.extend(self.head(version));
+        }
+        store
+            .write_commit(&self.id, version, &com.commit_id, content)
+            .await
+            .map_err(ShelfError::Storage)?;
+        insert_commit(conn, prompt_id, version, &com).await?;
+        let updated_at = Utc::now();
+        PromptVersions::update_many()
+            .col_expr(prompt_versions::Column::UpdatedAt, Expr::value(updated_at))
+            .filter(prompt_versions::Column::PromptId.eq(prompt_id))
+            .filter(prompt_versions::Column::Version.eq(version))
+            .exec(conn)
+            .await?;
         let node = self
             .nodes
             .iter_mut()
             .find(|n| n.version == version)
-            .ok_or_else(|| anyhow!("Version {} not found!", version))?;
+            .ok_or_else(|| version_not_found(version))?;
         node.commits.push(com);
-        node.updated_at = Utc::now();
+        node.updated_at = updated_at;
         Ok(())
     }
-    pub async fn get_commit(&self, version: &str, commit_id: &str) -> Result<PromptCommit> {
+    pub async fn get_commit(&self, version: &str, commit_id: &str) -> ShelfResult<PromptCommit> {
         let node = self
             .nodes
             .iter()
             .find(|n| n.version == version)
-            .ok_or_else(|| anyhow!("Version {} not found!", version))?;
+            .ok_or_else(|| version_not_found(version))?;
         let com = node
             .commits
             .iter()
             .find(|c| c.commit_id == commit_id)
-            .ok_or_else(|| anyhow!("Version {} not found!", version))?;
+            .ok_or_else(|| commit_not_found(commit_id))?;
         Ok(com.to_owned())
     }
-    pub async fn get_content(prompt_id: &str, version: &str, commit_id: &str) -> Result<String> {
-        let save_path = find_commit(prompt_id, version, commit_id)?;
-        let content = fs::read_to_string(save_path).await?;
-        Ok(content)
+    pub async fn get_content(
+        store: &dyn PromptStore,
+        prompt_id: &str,
+        version: &str,
+        commit_id: &str,
+    ) -> ShelfResult<String> {
+        store
+            .read_commit(prompt_id, version, commit_id)
+            .await
+            .map_err(ShelfError::Storage)
+    }
+    /// The newest commit of `version`, or the commit it was branched from.
+    pub fn head(&self, version: &str) -> Option<String> {
+        let node = self.nodes.iter().find(|n| n.version == version)?;
+        node.commits
+            .last()
+            .map(|c| c.commit_id.clone())
+            .or_else(|| node.base_commit.clone())
+    }
+    /// Finds a commit of any version, with the version it belongs to.
+    pub fn commit_by_id(&self, commit_id: &str) -> ShelfResult<(&str, &PromptCommit)> {
+        self.locate(commit_id)
+            .map(|(node, idx)| (node.version.as_str(), &node.commits[idx]))
+            .ok_or_else(|| commit_not_found(commit_id))
+    }
+    fn locate(&self, commit_id: &str) -> Option<(&PromptNode, usize)> {
+        self.nodes.iter().find_map(|n| {
+            n.commits
+                .iter()
+                .position(|c| c.commit_id == commit_id)
+                .map(|idx| (n, idx))
+        })
+    }
+    /// Commits made before parents were recorded descend from the previous
+    /// commit of their version, or from its base.
+    pub fn parents_of(&self, commit_id: &str) -> Vec<String> {
+        let Some((node, idx)) = self.locate(commit_id) else {
+            return Vec::new();
+        };
+        let com = &node.commits[idx];
+        if !com.parents.is_empty() {
+            com.parents.clone()
+        } else if idx > 0 {
+            vec![node.commits[idx - 1].commit_id.clone()]
+        } else {
+            node.base_commit.iter().cloned().collect()
+        }
+    }
+    /// `commit_id` and every commit it descends from.
+    pub fn ancestors(&self, commit_id: &str) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        let mut todo = vec![commit_id.to_string()];
+        while let Some(id) = todo.pop() {
+            if seen.insert(id.clone()) {
+                todo.extend(self.parents_of(&id));
+            }
+        }
+        seen
+    }
+    /// The nearest commit both `left` and `right` descend from.
+    pub fn merge_base(&self, left: &str, right: &str) -> Option<String> {
+        let left = self.ancestors(left);
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([right.to_string()]);
+        while let Some(id) = queue.pop_front() {
+            if left.contains(&id) {
+                return Some(id);
+            }
+            if seen.insert(id.clone()) {
+                queue.extend(self.parents_of(&id));
+            }
+        }
+        None
+    }
+    fn find_commit(&self, version: &str, commit_id: &str) -> Option<&PromptCommit> {
+        self.nodes
+            .iter()
+            .filter(|n| n.version == version)
+            .flat_map(|n| n.commits.iter())
+            .find(|c| c.commit_id == commit_id)
     }
-    pub async fn prev_commit(&self, version: &str, commit_id: &str) -> Result<String> {
+    pub async fn prev_commit(&self, version: &str, commit_id: &str) -> ShelfResult<String> {
         let node = self
             .nodes
             .iter()
             .find(|n| n.version == version)
-            .ok_or_else(|| anyhow!("Version {} not found!", version))?;
+            .ok_or_else(|| version_not_found(version))?;
         let idx = node
             .commits
             .iter()
             .position(|c| c.commit_id == commit_id)
-            .ok_or_else(|| anyhow!("Commit ID not found"))?;
+            .ok_or_else(|| commit_not_found(commit_id))?;
         if idx == 0 {
-            return Err(anyhow!("No previous commit id found"));
+            return Err(ShelfError::Conflict(
+                "No previous commit id found".to_string(),
+            ));
         }
-        let prev_commit = node
-            .commits
-            .get(idx - 1)
-            .ok_or_else(|| anyhow!("Previous commit not found"))?;
-        Ok(prev_commit.commit_id.clone())
+        Ok(node.commits[idx - 1].commit_id.clone())
     }
+    /// Diffs the `left` commit of this prompt with the `right` commit of
+    /// `other`, which is this prompt again unless comparing a fork.
     pub async fn diff_content(
         &self,
-        left_version: &str,
-        right_version: &str,
-        left_commit: &str,
-        right_commit: &str,
-    ) -> Result<String> {
-        let left_content = Prompts::get_content(&self.id, left_version, left_commit)
-            .await
-            .unwrap_or(String::new());
-        let right_content = Prompts::get_content(&self.id, right_version, right_commit)
-            .await
-            .unwrap_or(String::new());
+        store: &dyn PromptStore,
+        left: &LabelTarget,
+        other: &Prompts,
+        right: &LabelTarget,
+        opts: &DiffOptions,
+    ) -> ShelfResult<DiffResponse> {
+        let left_content =
+            Prompts::get_content(store, &self.id, &left.version, &left.commit_id).await?;
+        let right_content =
+            Prompts::get_content(store, &other.id, &right.version, &right.commit_id).await?;
 
-        let diff = TextDiff::from_lines(&left_content, &right_content);
-        let mut res = String::new();
-        for change in diff.iter_all_changes() {
-            let sign = match change.tag() {
-                ChangeTag::Delete => "-",
-                ChangeTag::Insert => "+",
-                ChangeTag::Equal => " ",
+        let left = self.find_commit(&left.version, &left.commit_id);
+        let right = other.find_commit(&right.version, &right.commit_id);
+        let content_type = |c: Option<&PromptCommit>| c.map(|c| c.content_type).unwrap_or_default();
+        let left_config = left.and_then(|c| c.model_config.as_ref());
+        let right_config = right.and_then(|c| c.model_config.as_ref());
+        let configs = (left_config.is_some() || right_config.is_some()).then(|| {
+            let pretty = |c: Option<&ModelConfig>| {
+                c.map(|c| serde_json::to_string_pretty(c).unwrap() + "\n")
+                    .unwrap_or_default()
             };
-            write!(res, "{sign}{change}")?;
+            (pretty(left_config), pretty(right_config))
+        });
+        if opts.format == DiffFormat::Json {
+            return Ok(DiffResponse::Json {
+                content: diff::report(&left_content, &right_content, opts),
+                model_config: configs.map(|(l, r)| diff::report(&l, &r, opts)),
+            });
         }
-        Ok(res)
+
+        // with `context` the text is a patch of the stored content; otherwise
+        // two chats are diffed per message, anything else as text
+        let mut res = String::new();
+        if opts.context.is_none()
+            && content_type(left) == ContentType::Chat
+            && content_type(right) == ContentType::Chat
+            && let (Ok(Content::Chat(l)), Ok(Content::Chat(r))) = (
+                Content::parse(ContentType::Chat, left_content.clone()),
+                Content::parse(ContentType::Chat, right_content.clone()),
+            )
+        {
+            res = chat::diff(&l, &r, opts);
+        } else {
+            diff::write_text(&mut res, &left_content, &right_content, opts);
+        }
+        if let Some((l, r)) = configs
+            && opts.context.is_none()
+        {
+            res.push_str(" @@ model_config @@\n");
+            diff::write_text(&mut res, &l, &r, opts);
+        }
+        Ok(DiffResponse::Text(res))
+    }
+}
+
+/// The `+/-` text of a diff, or its structure for `format: json`.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum DiffResponse {
+    Text(String),
+    Json {
+        content: DiffReport,
+        /// diff of the pretty printed model configs, when either commit has one
+        #[serde(skip_serializing_if = "Option::is_none")]
+        model_config: Option<DiffReport>,
+    },
+}
+
+async fn insert_commit<C: ConnectionTrait>(
+    conn: &C,
+    prompt_id: u64,
+    version: &str,
+    com: &PromptCommit,
+) -> ShelfResult<()> {
+    PromptCommits::insert(prompt_commits::ActiveModel {
+        prompt_id: Set(prompt_id),
+        version: Set(version.to_string()),
+        commit_id: Set(com.commit_id.clone()),
+        author: Set(com.author.clone()),
+        desp: Set(com.desp.clone()),
+        content_type: Set(com.content_type.as_str().to_string()),
+        variables: Set(com.variables.as_ref().map(|v| json!(v))),
+        model_config: Set(com.model_config.as_ref().map(|m| json!(m))),
+        output_schema: Set(com.output_schema.clone()),
+        parents: Set((!com.parents.is_empty()).then(|| json!(com.parents))),
+        created_at: Set(com.created_at),
+        ..Default::default()
+    })
+    .exec(conn)
+    .await?;
+    Ok(())
+}
+
+fn version_not_found(version: &str) -> ShelfError {
+    ShelfError::NotFound(format!("Version {version} not found!"))
+}
+
+fn commit_not_found(commit_id: &str) -> ShelfError {
+    ShelfError::NotFound(format!("Commit {commit_id} not found!"))
+}
+
+/// Version names are bounded by the `prompt_versions.version` column.
+fn check_version_len(version: &str) -> ShelfResult<()> {
+    if version.len() > MAX_VERSION_LEN {
+        return Err(ShelfError::Validation(format!(
+            "Version {version} is longer than {MAX_VERSION_LEN} bytes"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(id: &str, parents: &[&str]) -> PromptCommit {
+        let mut com = PromptCommit::new("a@example.com".to_string(), id.to_string());
+        com.commit_id = id.to_string();
+        com.parents = parents.iter().map(|p| p.to_string()).collect();
+        com
+    }
+
+    fn node(version: &str, base: Option<&str>, commits: Vec<PromptCommit>) -> PromptNode {
+        let mut node = PromptNode::new(version.to_string(), base.map(str::to_string));
+        node.commits = commits;
+        node
+    }
+
+    /// `v1`: c1 - c2 - c3 - m, `v2` branched from c2: d1 - d2, m merges d2.
+    /// c1..c3 and d1 predate recorded parents.
+    fn history() -> Prompts {
+        let mut prompt = Prompts::new("p".to_string());
+        prompt.nodes = vec![
+            node(
+                "v1",
+                None,
+                vec![
+                    commit("c1", &[]),
+                    commit("c2", &[]),
+                    commit("c3", &[]),
+                    commit("m", &["c3", "d2"]),
+                ],
+            ),
+            node(
+                "v2",
+                Some("c2"),
+                vec![commit("d1", &[]), commit("d2", &["d1"])],
+            ),
+        ];
+        prompt
+    }
+
+    fn set(ids: &[&str]) -> HashSet<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn ancestors_cross_the_branch_point() {
+        let prompt = history();
+        assert_eq!(prompt.ancestors("d2"), set(&["d2", "d1", "c2", "c1"]));
+        assert_eq!(
+            prompt.ancestors("m"),
+            set(&["m", "c3", "c2", "c1", "d2", "d1"])
+        );
+    }
+
+    #[test]
+    fn merge_base_of_branches_is_the_branch_point() {
+        let prompt = history();
+        assert_eq!(prompt.merge_base("c3", "d2").as_deref(), Some("c2"));
+        assert_eq!(prompt.merge_base("d1", "c3").as_deref(), Some("c2"));
+    }
+
+    #[test]
+    fn merge_base_after_a_merge_is_the_merged_head() {
+        let prompt = history();
+        assert_eq!(prompt.merge_base("m", "d2").as_deref(), Some("d2"));
+        assert_eq!(prompt.merge_base("c1", "m").as_deref(), Some("c1"));
+    }
+
+    #[test]
+    fn unrelated_commits_have_no_merge_base() {
+        let mut prompt = history();
+        prompt.nodes.push(node("v3", None, vec![commit("e1", &[])]));
+        assert_eq!(prompt.merge_base("e1", "d2"), None);
     }
 }
@@ -0,0 +1,292 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+};
+
+use axum::{Extension, extract::State};
+use futures::{FutureExt, future::BoxFuture};
+use sea_orm::{ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Serialize;
+
+use super::{
+    access::{AuthorizedPrompt, PromptRole, Read, authorize, visible_to},
+    common::{AppResponse, AppState, Prompts},
+    error::{ShelfError, ShelfResult},
+    label::{self, DEFAULT_LABEL, LabelTarget},
+    middleware::TokenClaims,
+};
+use crate::{
+    content::{Content, ContentType},
+    db::{
+        prompt_includes::{self, Entity as PromptIncludes},
+        prompt_labels::{self, Entity as PromptLabels},
+        prompts::{self, Entity as PromptData},
+    },
+    store::PromptStore,
+    template::include::{self, Include, Selector},
+};
+
+/// How deep includes may nest below the prompt being rendered.
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// A commit whose includes are being expanded. `target` is `None` for a commit
+/// being created, which any label of its prompt may come to point at.
+struct Expanding {
+    prompt_id: u64,
+    target: Option<LabelTarget>,
+}
+
+impl Expanding {
+    /// Whether `inc`, resolved to `target`, leads back into this commit.
+    fn reached_by(&self, inc: &Include, target: &LabelTarget) -> bool {
+        if self.prompt_id != inc.prompt_id {
+            return false;
+        }
+        match &self.target {
+            Some(own) => own.version == target.version && own.commit_id == target.commit_id,
+            None => !matches!(inc.selector, Selector::Commit { .. }),
+        }
+    }
+}
+
+impl fmt::Display for Expanding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            Some(t) => write!(f, "{}@{}/{}", self.prompt_id, t.version, t.commit_id),
+            None => write!(f, "{}", self.prompt_id),
+        }
+    }
+}
+
+/// Replaces every include directive of `content` by the content it references,
+/// recursively. `content` is the commit `target` of `prompt_id`, or a commit
+/// being created when `target` is `None`. The caller needs the viewer role on
+/// every included prompt.
+pub async fn expand(
+    conn: &DatabaseConnection,
+    store: &dyn PromptStore,
+    user_id: i64,
+    prompt_id: u64,
+    target: Option<&LabelTarget>,
+    content: String,
+) -> ShelfResult<String> {
+    let mut stack = vec![Expanding {
+        prompt_id,
+        target: target.cloned(),
+    }];
+    expand_nested(conn, store, user_id, content, &mut stack).await
+}
+
+/// [`expand`] applied to every part of `content`, e.g. each message of a chat.
+pub async fn expand_content(
+    conn: &DatabaseConnection,
+    store: &dyn PromptStore,
+    user_id: i64,
+    prompt_id: u64,
+    target: Option<&LabelTarget>,
+    content: Content,
+) -> ShelfResult<Content> {
+    let mut parts = Vec::new();
+    for part in content.parts() {
+        parts.push(expand(conn, store, user_id, prompt_id, target, part.to_string()).await?);
+    }
+    Ok(content.with_parts(parts))
+}
+
+/// `stack` holds the commits being expanded, outermost first.
+fn expand_nested<'a>(
+    conn: &'a DatabaseConnection,
+    store: &'a dyn PromptStore,
+    user_id: i64,
+    content: String,
+    stack: &'a mut Vec<Expanding>,
+) -> BoxFuture<'a, ShelfResult<String>> {
+    async move {
+        let includes = include::includes(&content).map_err(ShelfError::Validation)?;
+        if includes.is_empty() {
+            return Ok(content);
+        }
+        if stack.len() > MAX_INCLUDE_DEPTH {
+            return Err(ShelfError::Validation(format!(
+                "Includes nest deeper than {MAX_INCLUDE_DEPTH} levels"
+            )));
+        }
+        let mut res = String::with_capacity(content.len());
+        let mut pos = 0;
+        for inc in includes {
+            let (prompt, target) = resolve(conn, user_id, &inc)
+                .await
+                .map_err(|e| in_include(&inc.reference, e))?;
+            let next = Expanding {
+                prompt_id: inc.prompt_id,
+                target: Some(target.clone()),
+            };
+            if stack.iter().any(|e| e.reached_by(&inc, &target)) {
+                let path: Vec<String> =
+                    stack.iter().chain([&next]).map(|e| e.to_string()).collect();
+                return Err(ShelfError::Validation(format!(
+                    "Include cycle {}",
+                    path.join(" -> ")
+                )));
+            }
+            let included = fetch(conn, store, &prompt, &target, &inc)
+                .await
+                .map_err(|e| in_include(&inc.reference, e))?;
+            stack.push(next);
+            let included = expand_nested(conn, store, user_id, included, stack).await?;
+            stack.pop();
+            res.push_str(&content[pos..inc.span.start]);
+            res.push_str(&included);
+            pos = inc.span.end;
+        }
+        res.push_str(&content[pos..]);
+        Ok(res)
+    }
+    .boxed()
+}
+
+fn in_include(reference: &str, e: ShelfError) -> ShelfError {
+    match e {
+        ShelfError::NotFound(msg) => ShelfError::NotFound(format!("Include {reference}: {msg}")),
+        ShelfError::Forbidden(msg) => ShelfError::Forbidden(format!("Include {reference}: {msg}")),
+        other => other,
+    }
+}
+
+/// The included prompt and the commit `inc` selects.
+async fn resolve(
+    conn: &DatabaseConnection,
+    user_id: i64,
+    inc: &Include,
+) -> ShelfResult<(prompts::Model, LabelTarget)> {
+    let (prompt, _) = authorize(conn, user_id, inc.prompt_id, PromptRole::Viewer).await?;
+    let target = match &inc.selector {
+        Selector::Latest => label::resolve(conn, &prompt, None).await?,
+        Selector::Label(name) => label::resolve(conn, &prompt, Some(name)).await?,
+        Selector::Commit { version, commit_id } => LabelTarget {
+            version: version.clone(),
+            commit_id: commit_id.clone(),
+        },
+    };
+    Ok((prompt, target))
+}
+
+async fn fetch(
+    conn: &DatabaseConnection,
+    store: &dyn PromptStore,
+    prompt: &prompts::Model,
+    target: &LabelTarget,
+    inc: &Include,
+) -> ShelfResult<String> {
+    let commit = Prompts::load(conn, prompt)
+        .await?
+        .get_commit(&target.version, &target.commit_id)
+        .await?;
+    // a list of messages has no place inside a text
+    if commit.content_type == ContentType::Chat {
+        return Err(ShelfError::Validation(format!(
+            "Include {}: chat prompts cannot be included",
+            inc.reference
+        )));
+    }
+    Prompts::get_content(store, &prompt.file_key, &target.version, &target.commit_id).await
+}
+
+/// Remembers which prompts a new commit includes, for [`included_by`].
+pub async fn record_includes(
+    conn: &DatabaseConnection,
+    prompt_id: u64,
+    version: &str,
+    commit_id: &str,
+    content: &Content,
+) -> ShelfResult<()> {
+    let mut found = Vec::new();
+    for part in content.parts() {
+        found.extend(include::includes(part).map_err(ShelfError::Validation)?);
+    }
+    let rows: Vec<prompt_includes::ActiveModel> = found
+        .into_iter()
+        .map(|inc| prompt_includes::ActiveModel {
+            prompt_id: Set(prompt_id),
+            version: Set(version.to_string()),
+            commit_id: Set(commit_id.to_string()),
+            included_id: Set(inc.prompt_id),
+            reference: Set(inc.reference),
+            ..Default::default()
+        })
+        .collect();
+    if !rows.is_empty() {
+        PromptIncludes::insert_many(rows).exec(conn).await?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct IncludedBy {
+    prompt_id: u64,
+    name: String,
+    version: String,
+    commit_id: String,
+    reference: String,
+    /// labels of the including prompt that currently point at this commit
+    labels: Vec<String>,
+}
+
+/// Commits of other prompts that include this one, limited to prompts the
+/// caller can see.
+pub async fn included_by(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    auth: AuthorizedPrompt<Read>,
+) -> AppResponse<Vec<IncludedBy>> {
+    let rows = match PromptIncludes::find()
+        .filter(prompt_includes::Column::IncludedId.eq(auth.prompt.id))
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let ids: HashSet<u64> = rows.iter().map(|r| r.prompt_id).collect();
+    let includers: HashMap<u64, prompts::Model> = match PromptData::find()
+        .filter(prompts::Column::Id.is_in(ids.iter().copied()))
+        .filter(visible_to(claims.id))
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(p) => p.into_iter().map(|p| (p.id, p)).collect(),
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let labels = match PromptLabels::find()
+        .filter(prompt_labels::Column::PromptId.is_in(includers.keys().copied()))
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(l) => l,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let res = rows
+        .into_iter()
+        .filter_map(|row| {
+            let includer = includers.get(&row.prompt_id)?;
+            let mut live: Vec<String> = labels
+                .iter()
+                .filter(|l| l.prompt_id == row.prompt_id && l.commit_id == row.commit_id)
+                .map(|l| l.name.clone())
+                .collect();
+            if includer.latest_commit.as_deref() == Some(row.commit_id.as_str()) {
+                live.insert(0, DEFAULT_LABEL.to_string());
+            }
+            Some(IncludedBy {
+                prompt_id: row.prompt_id,
+                name: includer.name.clone(),
+                version: row.version,
+                commit_id: row.commit_id,
+                reference: row.reference,
+                labels: live,
+            })
+        })
+        .collect();
+    AppResponse::ok("Query includers finished".to_string(), Some(res))
+}
@@ -10,27 +10,62 @@
 //                                                                            //
 // ************************************************************************** //
 
-use std::{env, sync::atomic::AtomicBool};
+use std::env;
 
 use super::middleware::JwtConf;
+use crate::store::S3Conf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreBackend {
+    Fs,
+    Memory,
+    S3,
+}
+
+impl StoreBackend {
+    fn parse(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "memory" => StoreBackend::Memory,
+            "s3" => StoreBackend::S3,
+            _ => StoreBackend::Fs,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub data_dir: String,
+    pub store_backend: StoreBackend,
+    pub s3_conf: S3Conf,
     pub jwt_conf: JwtConf,
 }
 
 impl Config {
     pub fn from_env() -> Self {
         let data_dir = env::var("DATA_DIR").unwrap_or("/data".to_string());
+        let store_backend = StoreBackend::parse(&env::var("STORE_BACKEND").unwrap_or_default());
         let secret = env::var("JWT_SECRET").unwrap_or("promptshelf".to_string());
         let expire = env::var("JWT_EXPIRE")
             .unwrap_or("168".to_string())
             .parse::<i64>()
             .unwrap();
+        let s3_conf = S3Conf {
+            bucket: env::var("S3_BUCKET").unwrap_or("promptshelf".to_string()),
+            prefix: env::var("S3_PREFIX").unwrap_or_default(),
+            endpoint: env::var("S3_ENDPOINT").unwrap_or_default(),
+            region: env::var("S3_REGION").unwrap_or("us-east-1".to_string()),
+            access_key: env::var("S3_ACCESS_KEY").unwrap_or_default(),
+            secret_key: env::var("S3_SECRET_KEY").unwrap_or_default(),
+            path_style: env::var("S3_PATH_STYLE")
+                .unwrap_or("true".to_string())
+                .parse::<bool>()
+                .unwrap_or(true),
+        };
 
         Config {
             data_dir,
+            store_backend,
+            s3_conf,
             jwt_conf: JwtConf { secret, expire },
         }
     }
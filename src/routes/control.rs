@@ -20,6 +20,7 @@ use tracing::error;
 
 use super::{
     common::{AppResponse, AppState},
+    error::{ShelfError, ShelfResult},
     middleware::{JwtAuth, TokenClaims},
 };
 
@@ -43,13 +44,23 @@ pub async fn is_admin(user_id: i64, conn: &DatabaseConnection) -> bool {
     user.role == "super_admin"
 }
 
+pub async fn require_admin(user_id: i64, conn: &DatabaseConnection) -> ShelfResult<()> {
+    if is_admin(user_id, conn).await {
+        Ok(())
+    } else {
+        Err(ShelfError::Forbidden(
+            "Only super_admin can perform this action".to_string(),
+        ))
+    }
+}
+
 pub async fn allow_register(
     State(data): State<Arc<AppState>>,
     Extension(claims): Extension<TokenClaims>,
     Json(payload): Json<ControlParams>,
 ) -> AppResponse<String> {
-    if !is_admin(claims.id, &data.sql_conn).await {
-        return AppResponse::internal_err("Only super_admin can perform this action");
+    if let Err(e) = require_admin(claims.id, &data.sql_conn).await {
+        return e.into();
     }
     data.allow_register.store(
         payload.enable_register,
@@ -72,12 +83,12 @@ pub async fn all_user(
     State(data): State<Arc<AppState>>,
     Extension(claims): Extension<TokenClaims>,
 ) -> AppResponse<Vec<UserInfo>> {
-    if !is_admin(claims.id, &data.sql_conn).await {
-        return AppResponse::internal_err("Only super_admin can perform this action");
+    if let Err(e) = require_admin(claims.id, &data.sql_conn).await {
+        return e.into();
     }
     let users = match Users::find().all(&data.sql_conn).await {
         Ok(u) => u,
-        Err(e) => return AppResponse::internal_err(format!("Failed to query users: {e}")),
+        Err(e) => return ShelfError::Db(e).into(),
     };
     let res = users
         .into_iter()
@@ -99,12 +110,12 @@ pub async fn delete_user(
     Extension(claims): Extension<TokenClaims>,
     Path(user_id): Path<i64>,
 ) -> AppResponse<String> {
-    if !is_admin(claims.id, &data.sql_conn).await {
-        return AppResponse::internal_err("Only super_admin can perform this action");
+    if let Err(e) = require_admin(claims.id, &data.sql_conn).await {
+        return e.into();
     }
     match Users::delete_by_id(user_id).exec(&data.sql_conn).await {
         Ok(_) => AppResponse::ok(format!("User {user_id} has been deleted"), None),
-        Err(e) => AppResponse::internal_err(format!("Failed to delete users: {e}")),
+        Err(e) => ShelfError::Db(e).into(),
     }
 }
 
@@ -119,8 +130,8 @@ pub async fn user_control(
     Extension(claims): Extension<TokenClaims>,
     Json(payload): Json<UserControlInfo>,
 ) -> AppResponse<String> {
-    if !is_admin(claims.id, &data.sql_conn).await {
-        return AppResponse::internal_err("Only super_admin can perform this action");
+    if let Err(e) = require_admin(claims.id, &data.sql_conn).await {
+        return e.into();
     }
     let invalid_user = users::ActiveModel {
         id: Set(payload.user_id),
@@ -129,7 +140,7 @@ pub async fn user_control(
     };
     match Users::update(invalid_user).exec(&data.sql_conn).await {
         Ok(_) => AppResponse::ok("User status has been changed".to_string(), None),
-        Err(e) => AppResponse::internal_err(format!("Failed to delete users: {e}")),
+        Err(e) => ShelfError::Db(e).into(),
     }
 }
 
@@ -147,8 +158,8 @@ pub async fn add_user(
     Extension(claims): Extension<TokenClaims>,
     Json(payload): Json<AddUserInfo>,
 ) -> AppResponse<String> {
-    if !is_admin(claims.id, &data.sql_conn).await {
-        return AppResponse::internal_err("Only super_admin can perform this action");
+    if let Err(e) = require_admin(claims.id, &data.sql_conn).await {
+        return e.into();
     }
     let salt = SaltString::generate(&mut OsRng);
     let hashed_password = Argon2::default()
@@ -165,7 +176,7 @@ pub async fn add_user(
     };
     match Users::insert(new_user).exec(&data.sql_conn).await {
         Ok(_) => AppResponse::ok("User has been added".to_string(), None),
-        Err(e) => AppResponse::internal_err(format!("Failed to add user: {e}")),
+        Err(e) => ShelfError::Db(e).into(),
     }
 }
 
@@ -183,15 +194,15 @@ pub async fn update_user(
     Path(user_id): Path<i32>,
     Json(payload): Json<UpdateUserInfo>,
 ) -> AppResponse<String> {
-    if !is_admin(claims.id, &data.sql_conn).await {
-        return AppResponse::internal_err("Only super_admin can perform this action");
+    if let Err(e) = require_admin(claims.id, &data.sql_conn).await {
+        return e.into();
     }
 
     // 查询该用户的ActiveModel（sea-orm 通过 find_by_id）
     let mut user: users::ActiveModel = match Users::find_by_id(user_id).one(&data.sql_conn).await {
         Ok(Some(u)) => u.into_active_model(),
-        Ok(None) => return AppResponse::bad_request("User not found"),
-        Err(e) => return AppResponse::internal_err(format!("DB error: {e}")),
+        Ok(None) => return ShelfError::NotFound("User not found".to_string()).into(),
+        Err(e) => return ShelfError::Db(e).into(),
     };
 
     if let Some(username) = payload.username {
@@ -219,7 +230,7 @@ pub async fn update_user(
 
     match user.update(&data.sql_conn).await {
         Ok(_) => AppResponse::ok("User updated".to_string(), None),
-        Err(e) => AppResponse::internal_err(format!("Failed to update user: {e}")),
+        Err(e) => ShelfError::Db(e).into(),
     }
 }
 
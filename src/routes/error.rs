@@ -0,0 +1,52 @@
+use axum::response::{IntoResponse, Response};
+use sea_orm::DbErr;
+use serde::Serialize;
+
+use super::common::{AppCode, AppResponse};
+
+/// Failures of the prompt and control routes, each carrying its own status code.
+#[derive(Debug, thiserror::Error)]
+pub enum ShelfError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Validation(String),
+    #[error("Storage error: {0}")]
+    Storage(anyhow::Error),
+    #[error("Cache error: {0}")]
+    Cache(String),
+    #[error("Database error: {0}")]
+    Db(#[from] DbErr),
+}
+
+pub type ShelfResult<T> = Result<T, ShelfError>;
+
+impl ShelfError {
+    pub fn code(&self) -> AppCode {
+        match self {
+            ShelfError::NotFound(_) => AppCode::NotFound,
+            ShelfError::Forbidden(_) => AppCode::Forbidden,
+            ShelfError::Conflict(_) => AppCode::Conflict,
+            ShelfError::Validation(_) => AppCode::UnprocessableEntity,
+            ShelfError::Storage(_) | ShelfError::Cache(_) | ShelfError::Db(_) => {
+                AppCode::InternalError
+            }
+        }
+    }
+}
+
+impl<T: Serialize> From<ShelfError> for AppResponse<T> {
+    fn from(e: ShelfError) -> Self {
+        AppResponse::new(e.code(), e.to_string(), None)
+    }
+}
+
+impl IntoResponse for ShelfError {
+    fn into_response(self) -> Response {
+        AppResponse::<()>::from(self).into_response()
+    }
+}
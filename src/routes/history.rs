@@ -0,0 +1,503 @@
+//! Commits derived from other commits: merges between versions, cherry-picks,
+//! reverts and patches, plus line history.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use axum::{
+    Extension,
+    extract::{Query, State},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::{DiffOp, TextDiff};
+
+use super::{
+    access::{AuthorizedJson, AuthorizedPrompt, PromptRole, Read, Write, role_denied},
+    common::{AppCode, AppResponse, AppState, PromptCommit, Prompts},
+    error::{ShelfError, ShelfResult},
+    middleware::TokenClaims,
+    prompt::{CommitResponse, check_content, save_commit},
+};
+use crate::{
+    content::Content,
+    db::prompts,
+    diff::{self, PatchRejection},
+    merge::{self, MergeConflict},
+};
+
+type MergeResult = Result<AppResponse<CommitResponse>, AppResponse<Vec<MergeConflict>>>;
+
+/// Where a derived commit goes and how it is described.
+struct NewCommit {
+    version: String,
+    /// empty for the head of `version`
+    parents: Vec<String>,
+    desp: String,
+    as_latest: bool,
+    reason: Option<String>,
+}
+
+/// A commit created by replaying the change from `base` to `theirs` onto
+/// `ours`, the head of the new commit's version.
+struct Replay {
+    ours: String,
+    /// `None` replays `theirs` as if it was created from nothing
+    base: Option<String>,
+    theirs: String,
+    commit: NewCommit,
+}
+
+fn head_of(config: &Prompts, version: &str) -> ShelfResult<String> {
+    config
+        .head(version)
+        .ok_or_else(|| ShelfError::NotFound(format!("Version {version} has no commits")))
+}
+
+/// Line-merges the replayed change into `ours` and commits the result with the
+/// metadata of `ours`. Overlapping edits answer `409` with the conflicts.
+async fn replay(
+    data: &AppState,
+    claims: TokenClaims,
+    prompt: prompts::Model,
+    config: &Prompts,
+    replay: Replay,
+) -> MergeResult {
+    let (ours_version, ours_commit) = config.commit_by_id(&replay.ours)?;
+    let (theirs_version, theirs_commit) = config.commit_by_id(&replay.theirs)?;
+    if ours_commit.content_type != theirs_commit.content_type {
+        return Err(ShelfError::Conflict(format!(
+            "Cannot merge {} content into {} content",
+            theirs_commit.content_type, ours_commit.content_type
+        ))
+        .into());
+    }
+
+    let store = data.store.as_ref();
+    let file_key = &prompt.file_key;
+    let base_content = match &replay.base {
+        Some(base) => {
+            let (base_version, _) = config.commit_by_id(base)?;
+            Prompts::get_content(store, file_key, base_version, base).await?
+        }
+        None => String::new(),
+    };
+    let ours_content = Prompts::get_content(store, file_key, ours_version, &replay.ours).await?;
+    let theirs_content =
+        Prompts::get_content(store, file_key, theirs_version, &replay.theirs).await?;
+    let merged =
+        merge::merge3(&base_content, &ours_content, &theirs_content).map_err(|conflicts| {
+            AppResponse::new(
+                AppCode::Conflict,
+                "Merge has conflicts".to_string(),
+                Some(conflicts),
+            )
+        })?;
+    // a line merge of two valid documents is not always valid
+    let res = commit_derived(data, claims, prompt, ours_commit, merged, replay.commit)
+        .await
+        .map_err(|e| match e {
+            ShelfError::Validation(e) => ShelfError::Validation(format!("Merge result: {e}")),
+            e => e,
+        })?;
+    Ok(AppResponse::ok(
+        "Create commit finished".to_string(),
+        Some(res),
+    ))
+}
+
+/// Commits `text` with the content type, variables, model config and output
+/// schema of `from`.
+async fn commit_derived(
+    data: &AppState,
+    claims: TokenClaims,
+    prompt: prompts::Model,
+    from: &PromptCommit,
+    text: String,
+    new: NewCommit,
+) -> ShelfResult<CommitResponse> {
+    let content_type = from.content_type;
+    let (_, content) = Content::from_value(Some(content_type), content_type, Value::String(text))
+        .map_err(|e| ShelfError::Validation(e.to_string()))?;
+
+    let mut commit = PromptCommit::new(claims.email, new.desp);
+    commit.content_type = content_type;
+    commit.variables = from.variables.clone();
+    commit.model_config = from.model_config.clone();
+    commit.output_schema = from.output_schema.clone();
+    commit.parents = new.parents;
+    let warnings = check_content(
+        &data.sql_conn,
+        data.store.as_ref(),
+        claims.id,
+        &prompt,
+        &content,
+        commit.variables.as_deref(),
+        false,
+    )
+    .await?;
+    save_commit(
+        data,
+        prompt,
+        &new.version,
+        &commit,
+        &content,
+        new.as_latest,
+        new.reason,
+    )
+    .await?;
+    Ok(CommitResponse {
+        commit_id: commit.commit_id,
+        warnings,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct MergeInfo {
+    /// version receiving the merge commit, merged at its head
+    version: String,
+    source_version: String,
+    /// merge this commit instead of the head of `source_version`
+    source_commit: Option<String>,
+    desp: Option<String>,
+    #[serde(default)]
+    as_latest: bool,
+    reason: Option<String>,
+}
+
+/// Three-way merges the head of `source_version` into the head of `version`
+/// using their nearest common ancestor. The merge commit keeps the metadata of
+/// the target head and has both heads as parents.
+pub async fn merge(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Write, MergeInfo>,
+) -> MergeResult {
+    if payload.as_latest && auth.role < PromptRole::Maintainer {
+        return Err(role_denied(PromptRole::Maintainer, auth.role).into());
+    }
+    let config = Prompts::load(&data.sql_conn, &auth.prompt).await?;
+    let ours = head_of(&config, &payload.version)?;
+    let theirs = match payload.source_commit {
+        Some(commit_id) => commit_id,
+        None => head_of(&config, &payload.source_version)?,
+    };
+    let (theirs_version, _) = config.commit_by_id(&theirs)?;
+    if config.ancestors(&ours).contains(&theirs) {
+        return Err(ShelfError::Conflict(format!(
+            "{theirs} is already merged into {}",
+            payload.version
+        ))
+        .into());
+    }
+    let base = config.merge_base(&ours, &theirs).ok_or_else(|| {
+        ShelfError::Conflict(format!("{ours} and {theirs} share no common ancestor"))
+    })?;
+    let desp = payload
+        .desp
+        .unwrap_or_else(|| format!("Merge {theirs_version}/{theirs} into {}", payload.version));
+    let plan = Replay {
+        commit: NewCommit {
+            version: payload.version,
+            parents: vec![ours.clone(), theirs.clone()],
+            desp,
+            as_latest: payload.as_latest,
+            reason: payload.reason,
+        },
+        ours,
+        base: Some(base),
+        theirs,
+    };
+    replay(&data, claims, auth.prompt, &config, plan).await
+}
+
+#[derive(Deserialize)]
+pub struct CherryPickInfo {
+    /// the commit whose change is copied
+    commit_id: String,
+    /// version receiving the change at its head
+    version: String,
+    desp: Option<String>,
+    #[serde(default)]
+    as_latest: bool,
+    reason: Option<String>,
+}
+
+/// Applies the change `commit_id` made to its parent onto the head of
+/// `version`. For a merge commit the change is taken against its first parent.
+/// The change is three-way merged rather than applied as a patch, so it still
+/// lands when the lines around it sit elsewhere in the target version.
+pub async fn cherry_pick(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Write, CherryPickInfo>,
+) -> MergeResult {
+    if payload.as_latest && auth.role < PromptRole::Maintainer {
+        return Err(role_denied(PromptRole::Maintainer, auth.role).into());
+    }
+    let config = Prompts::load(&data.sql_conn, &auth.prompt).await?;
+    let ours = head_of(&config, &payload.version)?;
+    let (source_version, source) = config.commit_by_id(&payload.commit_id)?;
+    let desp = payload.desp.unwrap_or_else(|| {
+        format!(
+            "Cherry-pick {source_version}/{}: {}",
+            payload.commit_id, source.desp
+        )
+    });
+    let plan = Replay {
+        ours,
+        base: config.parents_of(&payload.commit_id).into_iter().next(),
+        theirs: payload.commit_id,
+        commit: NewCommit {
+            version: payload.version,
+            // the new commit descends from the target head only
+            parents: Vec::new(),
+            desp,
+            as_latest: payload.as_latest,
+            reason: payload.reason,
+        },
+    };
+    replay(&data, claims, auth.prompt, &config, plan).await
+}
+
+#[derive(Deserialize)]
+pub struct RevertCommitInfo {
+    /// the commit whose change is undone
+    commit_id: String,
+    /// version receiving the revert commit, the version of `commit_id` by default
+    version: Option<String>,
+    #[serde(default)]
+    as_latest: bool,
+    reason: Option<String>,
+}
+
+/// Creates a commit on the head of `version` that undoes the change
+/// `commit_id` made to its first parent, leaving the history append-only.
+pub async fn revert_commit(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Write, RevertCommitInfo>,
+) -> MergeResult {
+    if payload.as_latest && auth.role < PromptRole::Maintainer {
+        return Err(role_denied(PromptRole::Maintainer, auth.role).into());
+    }
+    let config = Prompts::load(&data.sql_conn, &auth.prompt).await?;
+    let (source_version, source) = config.commit_by_id(&payload.commit_id)?;
+    let parent = config
+        .parents_of(&payload.commit_id)
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            ShelfError::Conflict(format!("{} has no parent to revert to", payload.commit_id))
+        })?;
+    let version = payload
+        .version
+        .unwrap_or_else(|| source_version.to_string());
+    let desp = format!(
+        "Revert {source_version}/{} ({})",
+        payload.commit_id, source.desp
+    );
+    let plan = Replay {
+        ours: head_of(&config, &version)?,
+        base: Some(payload.commit_id),
+        theirs: parent,
+        commit: NewCommit {
+            version,
+            parents: Vec::new(),
+            desp,
+            as_latest: payload.as_latest,
+            reason: payload.reason,
+        },
+    };
+    replay(&data, claims, auth.prompt, &config, plan).await
+}
+
+#[derive(Deserialize)]
+pub struct ApplyPatchInfo {
+    /// the commit the patch was made against, which must be the head of `version`
+    version: String,
+    commit_id: String,
+    /// a unified diff of the content
+    patch: String,
+    desp: Option<String>,
+    #[serde(default)]
+    as_latest: bool,
+    reason: Option<String>,
+}
+
+/// Applies a unified diff to the head of `version` and commits the result with
+/// the metadata of the head. Hunks that do not match answer `409` with the
+/// rejections.
+pub async fn apply_patch(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Write, ApplyPatchInfo>,
+) -> Result<AppResponse<CommitResponse>, AppResponse<Vec<PatchRejection>>> {
+    if payload.as_latest && auth.role < PromptRole::Maintainer {
+        return Err(role_denied(PromptRole::Maintainer, auth.role).into());
+    }
+    let config = Prompts::load(&data.sql_conn, &auth.prompt).await?;
+    let head = head_of(&config, &payload.version)?;
+    if head != payload.commit_id {
+        return Err(ShelfError::Conflict(format!(
+            "{} is not the head of {}, rebase the patch onto {head}",
+            payload.commit_id, payload.version
+        ))
+        .into());
+    }
+    // a version without commits of its own is headed by its base commit
+    let (base_version, base) = config.commit_by_id(&payload.commit_id)?;
+    let hunks = diff::parse_patch(&payload.patch).map_err(ShelfError::Validation)?;
+    let content = Prompts::get_content(
+        data.store.as_ref(),
+        &auth.prompt.file_key,
+        base_version,
+        &payload.commit_id,
+    )
+    .await?;
+    let patched = diff::apply_patch(&content, &hunks).map_err(|rejections| {
+        AppResponse::new(
+            AppCode::Conflict,
+            "Patch does not apply".to_string(),
+            Some(rejections),
+        )
+    })?;
+    let new = NewCommit {
+        desp: payload
+            .desp
+            .unwrap_or_else(|| format!("Apply patch to {}", payload.commit_id)),
+        version: payload.version,
+        parents: Vec::new(),
+        as_latest: payload.as_latest,
+        reason: payload.reason,
+    };
+    let res = commit_derived(&data, claims, auth.prompt, base, patched, new).await?;
+    Ok(AppResponse::ok(
+        "Create commit finished".to_string(),
+        Some(res),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct BlameParams {
+    version: String,
+    commit_id: String,
+}
+
+#[derive(Serialize)]
+pub struct BlameLine {
+    /// line number in the blamed commit, from 1
+    line: usize,
+    content: String,
+    /// the commit that last changed the line
+    commit_id: String,
+    author: String,
+    created_at: DateTime<Utc>,
+}
+
+/// Annotates each line of a commit with the commit that last changed it,
+/// diffing each commit against all of its parents. A line a merge took from
+/// either side is followed into that side.
+pub async fn blame(
+    State(data): State<Arc<AppState>>,
+    auth: AuthorizedPrompt<Read>,
+    Query(params): Query<BlameParams>,
+) -> AppResponse<Vec<BlameLine>> {
+    match blame_lines(&data, &auth.prompt, &params.version, &params.commit_id).await {
+        Ok(lines) => AppResponse::ok("Blame finished".to_string(), Some(lines)),
+        Err(e) => e.into(),
+    }
+}
+
+/// A line of the blamed commit and its index in the commit holding it.
+struct Pending {
+    line: usize,
+    idx: usize,
+}
+
+async fn blame_lines(
+    data: &AppState,
+    prompt: &prompts::Model,
+    version: &str,
+    commit_id: &str,
+) -> ShelfResult<Vec<BlameLine>> {
+    let config = Prompts::load(&data.sql_conn, prompt).await?;
+    config.get_commit(version, commit_id).await?;
+    let store = data.store.as_ref();
+    let text = Prompts::get_content(store, &prompt.file_key, version, commit_id).await?;
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+
+    let mut owners: Vec<Option<String>> = vec![None; lines.len()];
+    let mut texts = HashMap::from([(commit_id.to_string(), text.clone())]);
+    // lines still to attribute by the commit holding them, newest commit last;
+    // parents are older than their children, so a commit is normally visited
+    // once, after every commit passing lines to it
+    let mut todo: BTreeMap<(DateTime<Utc>, String), Vec<Pending>> = BTreeMap::new();
+    let (_, blamed) = config.commit_by_id(commit_id)?;
+    todo.insert(
+        (blamed.created_at, commit_id.to_string()),
+        (0..lines.len()).map(|i| Pending { line: i, idx: i }).collect(),
+    );
+    while let Some(((_, current), pending)) = todo.pop_last() {
+        let current_text = texts[&current].clone();
+        let new: Vec<&str> = current_text.split_inclusive('\n').collect();
+        // for each parent, where each line of `current` sits in it
+        let mut in_parents = Vec::new();
+        for parent in config.parents_of(&current) {
+            let (parent_version, parent_commit) = config.commit_by_id(&parent)?;
+            if !texts.contains_key(&parent) {
+                let text =
+                    Prompts::get_content(store, &prompt.file_key, parent_version, &parent).await?;
+                texts.insert(parent.clone(), text);
+            }
+            let old: Vec<&str> = texts[&parent].split_inclusive('\n').collect();
+            let mut in_parent = vec![None; new.len()];
+            for op in TextDiff::from_slices(&old, &new).ops() {
+                if let DiffOp::Equal {
+                    old_index,
+                    new_index,
+                    len,
+                } = *op
+                {
+                    for k in 0..len {
+                        in_parent[new_index + k] = Some(old_index + k);
+                    }
+                }
+            }
+            in_parents.push(((parent_commit.created_at, parent), in_parent));
+        }
+        // a line goes to the first parent that has it, or was changed here
+        for p in pending {
+            let found = in_parents
+                .iter()
+                .find_map(|(key, in_parent)| Some((key, in_parent[p.idx]?)));
+            match found {
+                Some((key, idx)) => todo
+                    .entry(key.clone())
+                    .or_default()
+                    .push(Pending { line: p.line, idx }),
+                None => owners[p.line] = Some(current.clone()),
+            }
+        }
+    }
+
+    lines
+        .into_iter()
+        .zip(owners)
+        .enumerate()
+        .map(|(i, (content, owner))| {
+            let owner = owner.unwrap_or_default();
+            let (_, commit) = config.commit_by_id(&owner)?;
+            Ok(BlameLine {
+                line: i + 1,
+                content: content.trim_end_matches('\n').to_string(),
+                commit_id: owner,
+                author: commit.author.clone(),
+                created_at: commit.created_at,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,426 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension,
+    extract::{Query, State},
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    access::{AuthorizedJson, AuthorizedPrompt, Manage, Read},
+    common::{AppResponse, AppState, Prompts},
+    error::{ShelfError, ShelfResult},
+    middleware::TokenClaims,
+};
+use crate::db::{
+    prompt_labels::{self, Entity as PromptLabels},
+    prompt_promotions::{self, Entity as PromptPromotions},
+    prompts::{self, Entity as PromptData},
+};
+
+/// The label kept in `prompts.latest_version`/`latest_commit`, used when a
+/// request names no label.
+pub const DEFAULT_LABEL: &str = "latest";
+
+/// The (version, commit) pair a label points at.
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelTarget {
+    pub version: String,
+    pub commit_id: String,
+}
+
+impl LabelTarget {
+    fn from_pair(version: Option<String>, commit_id: Option<String>) -> Option<Self> {
+        Some(Self {
+            version: version?,
+            commit_id: commit_id?,
+        })
+    }
+}
+
+fn check_name(name: &str) -> ShelfResult<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(ShelfError::Validation(format!(
+            "Invalid label name {name:?}, use at most 64 of [A-Za-z0-9._-]"
+        )))
+    }
+}
+
+async fn find_label<C: ConnectionTrait>(
+    conn: &C,
+    prompt_id: u64,
+    name: &str,
+) -> ShelfResult<Option<prompt_labels::Model>> {
+    Ok(PromptLabels::find()
+        .filter(prompt_labels::Column::PromptId.eq(prompt_id))
+        .filter(prompt_labels::Column::Name.eq(name))
+        .one(conn)
+        .await?)
+}
+
+/// Resolves `label` (default `latest`) of `prompt` to the commit it points at.
+pub async fn resolve(
+    conn: &DatabaseConnection,
+    prompt: &prompts::Model,
+    label: Option<&str>,
+) -> ShelfResult<LabelTarget> {
+    let name = label.unwrap_or(DEFAULT_LABEL);
+    if name == DEFAULT_LABEL {
+        return match (&prompt.latest_version, &prompt.latest_commit) {
+            (Some(version), Some(commit_id)) => Ok(LabelTarget {
+                version: version.clone(),
+                commit_id: commit_id.clone(),
+            }),
+            _ => Err(ShelfError::NotFound(
+                "Prompt has no latest commit".to_string(),
+            )),
+        };
+    }
+    find_label(conn, prompt.id, name)
+        .await?
+        .map(|l| LabelTarget {
+            version: l.version,
+            commit_id: l.commit_id,
+        })
+        .ok_or_else(|| ShelfError::NotFound(format!("Label {name} not found")))
+}
+
+/// The commit named by `version` and `commit_id` together, or else the one
+/// `label` points at.
+pub async fn resolve_target(
+    conn: &DatabaseConnection,
+    prompt: &prompts::Model,
+    version: Option<String>,
+    commit_id: Option<String>,
+    label: Option<&str>,
+) -> ShelfResult<LabelTarget> {
+    match (version, commit_id) {
+        (Some(version), Some(commit_id)) => Ok(LabelTarget { version, commit_id }),
+        (None, None) => resolve(conn, prompt, label).await,
+        _ => Err(ShelfError::Validation(
+            "version and commit_id must be given together".to_string(),
+        )),
+    }
+}
+
+/// Resolves the commit `label` pointed at the instant `at`, from the promotion
+/// history. Before the first recorded move the label held that move's previous
+/// target; a label without any recorded move is not resolved.
+pub async fn resolve_at(
+    conn: &DatabaseConnection,
+    prompt: &prompts::Model,
+    label: Option<&str>,
+    at: DateTime<Utc>,
+) -> ShelfResult<LabelTarget> {
+    let name = label.unwrap_or(DEFAULT_LABEL);
+    let history = || {
+        PromptPromotions::find()
+            .filter(prompt_promotions::Column::PromptId.eq(prompt.id))
+            .filter(prompt_promotions::Column::Label.eq(name))
+    };
+    let not_set = || ShelfError::NotFound(format!("Label {name} was not set at {at}"));
+    if let Some(p) = history()
+        .filter(prompt_promotions::Column::CreatedAt.lte(at))
+        .order_by_desc(prompt_promotions::Column::CreatedAt)
+        .order_by_desc(prompt_promotions::Column::Id)
+        .one(conn)
+        .await?
+    {
+        return LabelTarget::from_pair(p.version, p.commit_id).ok_or_else(not_set);
+    }
+    match history()
+        .order_by_asc(prompt_promotions::Column::Id)
+        .one(conn)
+        .await?
+    {
+        Some(p) => LabelTarget::from_pair(p.prev_version, p.prev_commit).ok_or_else(not_set),
+        None => Err(ShelfError::NotFound(format!(
+            "Label {name} has no history recorded before {at}"
+        ))),
+    }
+}
+
+async fn record<C: ConnectionTrait>(
+    conn: &C,
+    prompt_id: u64,
+    label: &str,
+    prev: Option<LabelTarget>,
+    next: Option<LabelTarget>,
+    actor: &str,
+    reason: Option<String>,
+) -> ShelfResult<()> {
+    let (prev_version, prev_commit) = prev.map(|t| (t.version, t.commit_id)).unzip();
+    let (version, commit_id) = next.map(|t| (t.version, t.commit_id)).unzip();
+    PromptPromotions::insert(prompt_promotions::ActiveModel {
+        prompt_id: Set(prompt_id),
+        label: Set(label.to_string()),
+        prev_version: Set(prev_version),
+        prev_commit: Set(prev_commit),
+        version: Set(version),
+        commit_id: Set(commit_id),
+        actor: Set(actor.to_string()),
+        reason: Set(reason),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    })
+    .exec(conn)
+    .await?;
+    Ok(())
+}
+
+/// Points `label` of `prompt` at `target` and records the move. Every move of a
+/// live pointer goes through here; callers check that the commit exists.
+pub async fn move_label(
+    conn: &DatabaseConnection,
+    prompt: prompts::Model,
+    label: &str,
+    target: LabelTarget,
+    actor: &str,
+    reason: Option<String>,
+) -> ShelfResult<()> {
+    check_name(label)?;
+    let txn = conn.begin().await?;
+    let prompt_id = prompt.id;
+    let prev = if label == DEFAULT_LABEL {
+        // the row again under lock, so concurrent moves record the right prev
+        let prompt = PromptData::find_by_id(prompt_id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| ShelfError::NotFound("Prompt id not exist!".to_string()))?;
+        let prev =
+            LabelTarget::from_pair(prompt.latest_version.clone(), prompt.latest_commit.clone());
+        let mut prompt: prompts::ActiveModel = prompt.into();
+        prompt.latest_version = Set(Some(target.version.clone()));
+        prompt.latest_commit = Set(Some(target.commit_id.clone()));
+        prompt.update(&txn).await?;
+        prev
+    } else {
+        match find_label(&txn, prompt_id, label).await? {
+            Some(existing) => {
+                let prev = LabelTarget {
+                    version: existing.version.clone(),
+                    commit_id: existing.commit_id.clone(),
+                };
+                let mut existing: prompt_labels::ActiveModel = existing.into();
+                existing.version = Set(target.version.clone());
+                existing.commit_id = Set(target.commit_id.clone());
+                existing.updated_at = Set(Utc::now());
+                existing.update(&txn).await?;
+                Some(prev)
+            }
+            None => {
+                PromptLabels::insert(prompt_labels::ActiveModel {
+                    prompt_id: Set(prompt_id),
+                    name: Set(label.to_string()),
+                    version: Set(target.version.clone()),
+                    commit_id: Set(target.commit_id.clone()),
+                    ..Default::default()
+                })
+                .exec(&txn)
+                .await?;
+                None
+            }
+        }
+    };
+    record(&txn, prompt_id, label, prev, Some(target), actor, reason).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct SetLabelInfo {
+    label: String,
+    version: String,
+    commit_id: String,
+    reason: Option<String>,
+}
+
+pub async fn set_label(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Manage, SetLabelInfo>,
+) -> AppResponse<LabelTarget> {
+    let prompt_config = match Prompts::load(&data.sql_conn, &auth.prompt).await {
+        Ok(p) => p,
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = prompt_config
+        .get_commit(&payload.version, &payload.commit_id)
+        .await
+    {
+        return e.into();
+    }
+    let target = LabelTarget {
+        version: payload.version,
+        commit_id: payload.commit_id,
+    };
+    match move_label(
+        &data.sql_conn,
+        auth.prompt,
+        &payload.label,
+        target.clone(),
+        &claims.email,
+        payload.reason,
+    )
+    .await
+    {
+        Ok(()) => AppResponse::ok(format!("Label {} moved", payload.label), Some(target)),
+        Err(e) => e.into(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct LabelInfo {
+    name: String,
+    version: String,
+    commit_id: String,
+    updated_at: DateTime<Utc>,
+}
+
+pub async fn list_label(
+    State(data): State<Arc<AppState>>,
+    auth: AuthorizedPrompt<Read>,
+) -> AppResponse<Vec<LabelInfo>> {
+    let labels = match PromptLabels::find()
+        .filter(prompt_labels::Column::PromptId.eq(auth.prompt.id))
+        .order_by_asc(prompt_labels::Column::Name)
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(l) => l,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let mut res = Vec::with_capacity(labels.len() + 1);
+    if let (Some(version), Some(commit_id)) =
+        (auth.prompt.latest_version, auth.prompt.latest_commit)
+    {
+        res.push(LabelInfo {
+            name: DEFAULT_LABEL.to_string(),
+            version,
+            commit_id,
+            updated_at: auth.prompt.updated_at,
+        });
+    }
+    res.extend(labels.into_iter().map(|l| LabelInfo {
+        name: l.name,
+        version: l.version,
+        commit_id: l.commit_id,
+        updated_at: l.updated_at,
+    }));
+    AppResponse::ok("List labels finished".to_string(), Some(res))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteLabelInfo {
+    label: String,
+    reason: Option<String>,
+}
+
+async fn remove_label(
+    conn: &DatabaseConnection,
+    prompt_id: u64,
+    label: &str,
+    actor: &str,
+    reason: Option<String>,
+) -> ShelfResult<()> {
+    if label == DEFAULT_LABEL {
+        return Err(ShelfError::Validation(format!(
+            "Label {DEFAULT_LABEL} can not be deleted"
+        )));
+    }
+    let txn = conn.begin().await?;
+    let existing = find_label(&txn, prompt_id, label)
+        .await?
+        .ok_or_else(|| ShelfError::NotFound(format!("Label {label} not found")))?;
+    let prev = LabelTarget {
+        version: existing.version.clone(),
+        commit_id: existing.commit_id.clone(),
+    };
+    PromptLabels::delete_by_id(existing.id).exec(&txn).await?;
+    record(&txn, prompt_id, label, Some(prev), None, actor, reason).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_label(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Manage, DeleteLabelInfo>,
+) -> AppResponse<String> {
+    match remove_label(
+        &data.sql_conn,
+        auth.prompt.id,
+        &payload.label,
+        &claims.email,
+        payload.reason,
+    )
+    .await
+    {
+        Ok(()) => AppResponse::ok(format!("Label {} deleted", payload.label), None),
+        Err(e) => e.into(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PromotionInfo {
+    label: String,
+    prev_version: Option<String>,
+    prev_commit: Option<String>,
+    version: Option<String>,
+    commit_id: Option<String>,
+    actor: String,
+    reason: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn label_history(
+    State(data): State<Arc<AppState>>,
+    auth: AuthorizedPrompt<Read>,
+    Query(params): Query<HistoryParams>,
+) -> AppResponse<Vec<PromotionInfo>> {
+    let mut query =
+        PromptPromotions::find().filter(prompt_promotions::Column::PromptId.eq(auth.prompt.id));
+    if let Some(label) = params.label {
+        query = query.filter(prompt_promotions::Column::Label.eq(label));
+    }
+    let history = match query
+        .order_by_desc(prompt_promotions::Column::Id)
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(h) => h,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let res = history
+        .into_iter()
+        .map(|p| PromotionInfo {
+            label: p.label,
+            prev_version: p.prev_version,
+            prev_commit: p.prev_commit,
+            version: p.version,
+            commit_id: p.commit_id,
+            actor: p.actor,
+            reason: p.reason,
+            created_at: p.created_at,
+        })
+        .collect();
+    AppResponse::ok("Query label history finished".to_string(), Some(res))
+}
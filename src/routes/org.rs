@@ -0,0 +1,400 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, Query, State},
+    routing::{delete, get, post},
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QuerySelect, TransactionTrait, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use tower_http::validate_request::ValidateRequestHeaderLayer;
+
+use super::{
+    access::SUBJECT_ORG,
+    common::{AppResponse, AppState},
+    control::is_admin,
+    error::ShelfError,
+    middleware::{JwtAuth, TokenClaims},
+    prompt::evict_prompts,
+};
+use crate::db::{
+    organizations::{self, Entity as Organizations},
+    prompt_permissions::{self, Entity as PromptPermissions},
+    prompts::{self, Entity as PromptData},
+    user_organizations::{self, Entity as UserOrganizations},
+    users::{self, Entity as Users},
+};
+
+pub async fn is_member(user_id: i64, org_id: i64, conn: &DatabaseConnection) -> bool {
+    matches!(
+        UserOrganizations::find_by_id((user_id, org_id))
+            .one(conn)
+            .await,
+        Ok(Some(_))
+    )
+}
+
+/// Loads the organization and checks that `user_id` is its admin or a `super_admin`.
+async fn manageable_org<T: Serialize>(
+    conn: &DatabaseConnection,
+    org_id: i64,
+    user_id: i64,
+) -> Result<organizations::Model, AppResponse<T>> {
+    let org = match Organizations::find_by_id(org_id).one(conn).await {
+        Ok(Some(o)) => o,
+        Ok(None) => return Err(AppResponse::not_found("Organization not exist")),
+        Err(e) => return Err(ShelfError::Db(e).into()),
+    };
+    if org.admin_id != user_id && !is_admin(user_id, conn).await {
+        return Err(AppResponse::forbidden(
+            "Only the organization admin or super_admin can perform this action",
+        ));
+    }
+    Ok(org)
+}
+
+#[derive(Deserialize)]
+pub struct CreateOrgInfo {
+    name: String,
+    description: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OrgId {
+    id: i64,
+}
+
+pub async fn create_org(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    Json(payload): Json<CreateOrgInfo>,
+) -> AppResponse<OrgId> {
+    let txn = match data.sql_conn.begin().await {
+        Ok(t) => t,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let org = organizations::ActiveModel {
+        name: Set(payload.name),
+        admin_id: Set(claims.id),
+        description: Set(payload.description),
+        ..Default::default()
+    };
+    let org_id = match Organizations::insert(org).exec(&txn).await {
+        Ok(o) => o.last_insert_id,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let member = user_organizations::ActiveModel {
+        user_id: Set(claims.id),
+        org_id: Set(org_id),
+    };
+    if let Err(e) = UserOrganizations::insert(member).exec(&txn).await {
+        return ShelfError::Db(e).into();
+    }
+    if let Err(e) = txn.commit().await {
+        return ShelfError::Db(e).into();
+    }
+    AppResponse::ok(
+        "Create organization finished".to_string(),
+        Some(OrgId { id: org_id }),
+    )
+}
+
+#[derive(Serialize)]
+pub struct OrgInfo {
+    id: i64,
+    name: String,
+    admin_id: i64,
+    description: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+pub async fn list_org(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+) -> AppResponse<Vec<OrgInfo>> {
+    let org_ids: Vec<i64> = match UserOrganizations::find()
+        .select_only()
+        .column(user_organizations::Column::OrgId)
+        .filter(user_organizations::Column::UserId.eq(claims.id))
+        .into_tuple()
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(ids) => ids,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let orgs = match Organizations::find()
+        .filter(organizations::Column::Id.is_in(org_ids))
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let res = orgs
+        .into_iter()
+        .map(|o| OrgInfo {
+            id: o.id,
+            name: o.name,
+            admin_id: o.admin_id,
+            description: o.description,
+            created_at: o.created_at,
+            updated_at: o.updated_at,
+        })
+        .collect();
+    AppResponse::ok("Query organizations finished".to_string(), Some(res))
+}
+
+#[derive(Deserialize)]
+pub struct OrgParams {
+    org_id: i64,
+}
+
+#[derive(Serialize)]
+pub struct MemberInfo {
+    id: i64,
+    username: String,
+    email: String,
+    is_admin: bool,
+}
+
+pub async fn list_member(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    Query(params): Query<OrgParams>,
+) -> AppResponse<Vec<MemberInfo>> {
+    let org = match Organizations::find_by_id(params.org_id)
+        .one(&data.sql_conn)
+        .await
+    {
+        Ok(Some(o)) => o,
+        Ok(None) => return AppResponse::not_found("Organization not exist"),
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    if !is_member(claims.id, org.id, &data.sql_conn).await
+        && !is_admin(claims.id, &data.sql_conn).await
+    {
+        return AppResponse::forbidden("Not a member of this organization");
+    }
+    let user_ids: Vec<i64> = match UserOrganizations::find()
+        .select_only()
+        .column(user_organizations::Column::UserId)
+        .filter(user_organizations::Column::OrgId.eq(org.id))
+        .into_tuple()
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(ids) => ids,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let members = match Users::find()
+        .filter(users::Column::Id.is_in(user_ids))
+        .all(&data.sql_conn)
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let res = members
+        .into_iter()
+        .map(|u| MemberInfo {
+            id: u.id,
+            is_admin: u.id == org.admin_id,
+            username: u.username,
+            email: u.email,
+        })
+        .collect();
+    AppResponse::ok("Query members finished".to_string(), Some(res))
+}
+
+#[derive(Deserialize)]
+pub struct AddMemberInfo {
+    org_id: i64,
+    user_id: Option<i64>,
+    email: Option<String>,
+}
+
+pub async fn add_member(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    Json(payload): Json<AddMemberInfo>,
+) -> AppResponse<String> {
+    let org = match manageable_org(&data.sql_conn, payload.org_id, claims.id).await {
+        Ok(o) => o,
+        Err(e) => return e,
+    };
+    let query = match (payload.user_id, payload.email) {
+        (Some(id), _) => Users::find_by_id(id),
+        (None, Some(email)) => Users::find().filter(users::Column::Email.eq(email)),
+        (None, None) => return AppResponse::bad_request("Missing user_id or email"),
+    };
+    let user = match query.one(&data.sql_conn).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return AppResponse::not_found("User not exist"),
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    if is_member(user.id, org.id, &data.sql_conn).await {
+        return ShelfError::Conflict("User is already a member".to_string()).into();
+    }
+    let member = user_organizations::ActiveModel {
+        user_id: Set(user.id),
+        org_id: Set(org.id),
+    };
+    match UserOrganizations::insert(member).exec(&data.sql_conn).await {
+        Ok(_) => AppResponse::ok(format!("User {} has been added", user.id), None),
+        Err(e) => ShelfError::Db(e).into(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MemberParams {
+    org_id: i64,
+    user_id: i64,
+}
+
+pub async fn remove_member(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    Json(payload): Json<MemberParams>,
+) -> AppResponse<String> {
+    // members may always leave on their own
+    let org = if payload.user_id == claims.id {
+        match Organizations::find_by_id(payload.org_id)
+            .one(&data.sql_conn)
+            .await
+        {
+            Ok(Some(o)) => o,
+            Ok(None) => return AppResponse::not_found("Organization not exist"),
+            Err(e) => return ShelfError::Db(e).into(),
+        }
+    } else {
+        match manageable_org(&data.sql_conn, payload.org_id, claims.id).await {
+            Ok(o) => o,
+            Err(e) => return e,
+        }
+    };
+    if org.admin_id == payload.user_id {
+        return AppResponse::bad_request("Transfer the admin role before removing the admin");
+    }
+    match UserOrganizations::delete_by_id((payload.user_id, org.id))
+        .exec(&data.sql_conn)
+        .await
+    {
+        Ok(r) if r.rows_affected == 0 => AppResponse::not_found("User is not a member"),
+        Ok(_) => AppResponse::ok(format!("User {} has been removed", payload.user_id), None),
+        Err(e) => ShelfError::Db(e).into(),
+    }
+}
+
+/// Ids of the prompts owned by `org_id`.
+async fn org_prompts(conn: &DatabaseConnection, org_id: i64) -> Result<Vec<u64>, DbErr> {
+    PromptData::find()
+        .select_only()
+        .column(prompts::Column::Id)
+        .filter(prompts::Column::OrgId.eq(org_id))
+        .into_tuple()
+        .all(conn)
+        .await
+}
+
+pub async fn transfer_admin(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    Json(payload): Json<MemberParams>,
+) -> AppResponse<String> {
+    let org = match manageable_org(&data.sql_conn, payload.org_id, claims.id).await {
+        Ok(o) => o,
+        Err(e) => return e,
+    };
+    if !is_member(payload.user_id, org.id, &data.sql_conn).await {
+        return AppResponse::bad_request("New admin must be a member of the organization");
+    }
+    let new_admin = organizations::ActiveModel {
+        id: Set(org.id),
+        admin_id: Set(payload.user_id),
+        ..Default::default()
+    };
+    if let Err(e) = Organizations::update(new_admin).exec(&data.sql_conn).await {
+        return ShelfError::Db(e).into();
+    }
+    match org_prompts(&data.sql_conn, org.id).await {
+        Ok(ids) => evict_prompts(&data.redis_pool, &ids).await,
+        Err(e) => return ShelfError::Db(e).into(),
+    }
+    AppResponse::ok(
+        format!("Admin has been transferred to user {}", payload.user_id),
+        None,
+    )
+}
+
+pub async fn delete_org(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    Path(org_id): Path<i64>,
+) -> AppResponse<String> {
+    let org = match manageable_org(&data.sql_conn, org_id, claims.id).await {
+        Ok(o) => o,
+        Err(e) => return e,
+    };
+    let prompt_ids = match org_prompts(&data.sql_conn, org.id).await {
+        Ok(ids) => ids,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    let txn = match data.sql_conn.begin().await {
+        Ok(t) => t,
+        Err(e) => return ShelfError::Db(e).into(),
+    };
+    // prompts stay with their creators
+    if let Err(e) = PromptData::update_many()
+        .col_expr(prompts::Column::OrgId, Expr::value(Option::<i64>::None))
+        .filter(prompts::Column::OrgId.eq(org.id))
+        .exec(&txn)
+        .await
+    {
+        return ShelfError::Db(e).into();
+    }
+    if let Err(e) = UserOrganizations::delete_many()
+        .filter(user_organizations::Column::OrgId.eq(org.id))
+        .exec(&txn)
+        .await
+    {
+        return ShelfError::Db(e).into();
+    }
+    if let Err(e) = PromptPermissions::delete_many()
+        .filter(prompt_permissions::Column::SubjectType.eq(SUBJECT_ORG))
+        .filter(prompt_permissions::Column::SubjectId.eq(org.id))
+        .exec(&txn)
+        .await
+    {
+        return ShelfError::Db(e).into();
+    }
+    if let Err(e) = Organizations::delete_by_id(org.id).exec(&txn).await {
+        return ShelfError::Db(e).into();
+    }
+    if let Err(e) = txn.commit().await {
+        return ShelfError::Db(e).into();
+    }
+    evict_prompts(&data.redis_pool, &prompt_ids).await;
+    AppResponse::ok(format!("Organization {org_id} has been deleted"), None)
+}
+
+pub fn routes(app_state: Arc<AppState>) -> Router {
+    let jwt_auth = JwtAuth {
+        conf: Arc::new(app_state.config.jwt_conf.clone()),
+    };
+    Router::new()
+        .route("/create", post(create_org))
+        .route("/list", get(list_org))
+        .route("/members", get(list_member))
+        .route("/member/add", post(add_member))
+        .route("/member/remove", post(remove_member))
+        .route("/transfer", post(transfer_admin))
+        .route("/{org_id}", delete(delete_org))
+        .layer(ValidateRequestHeaderLayer::custom(jwt_auth))
+        .with_state(app_state)
+}
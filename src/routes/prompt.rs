@@ -1,11 +1,18 @@
-use futures::{StreamExt, stream};
 use std::sync::Arc;
 
 use crate::{
+    content::{Content, ContentError, ContentType},
     db::prompts::{self, Entity as PromptData},
-    init::{get_cache, set_cache},
+    diff::DiffOptions,
+    init::{del_cache, get_cache, set_cache},
+    model_config::ModelConfig,
+    output_schema::{self, OutputIssue},
+    store::PromptStore,
+    template::{
+        self, Engine, TemplateIssue,
+        schema::{self, Variable},
+    },
 };
-use anyhow::{Result, anyhow};
 use axum::{
     Extension, Json, Router,
     extract::{Query, State},
@@ -13,22 +20,35 @@ use axum::{
 };
 use chrono::{DateTime, Utc};
 use sea_orm::{
-    ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, EntityTrait, ModelTrait,
-    QueryFilter,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    ModelTrait, QueryFilter,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use tower_http::validate_request::ValidateRequestHeaderLayer;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use super::{
-    common::{AppResponse, AppState, MAX_CONCURRENT_TASKS, PromptCommit, Prompts},
-    finder::find_config,
+    access::{
+        self, AuthorizedJson, AuthorizedPrompt, Manage, Own, PromptRole, Read, Write, role_denied,
+        visible_to,
+    },
+    common::{AppCode, AppResponse, AppState, DiffResponse, PromptCommit, Prompts},
+    compose,
+    error::{ShelfError, ShelfResult},
+    history,
+    label::{self, DEFAULT_LABEL, LabelTarget},
     middleware::{JwtAuth, TokenClaims},
+    org::is_member,
 };
 
 #[derive(Debug, Deserialize)]
 pub struct PromptInfo {
     name: String,
+    org_id: Option<i64>,
+    template_engine: Option<String>,
+    /// type of commits that do not declare one
+    content_type: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,57 +61,117 @@ pub async fn create_prompt(
     Extension(claims): Extension<TokenClaims>,
     Json(payload): Json<PromptInfo>,
 ) -> AppResponse<CreateResponse> {
-    let prompt = Prompts::new(payload.name);
-    match prompt.save().await {
-        Ok(()) => info!("Prompt {} saved.", prompt.id()),
-        Err(e) => {
-            return AppResponse::internal_err(format!(
-                "Failed to save Prompt {}, {}",
-                prompt.id(),
-                e
-            ));
-        }
+    if let Some(org_id) = payload.org_id
+        && !is_member(claims.id, org_id, &data.sql_conn).await
+    {
+        return AppResponse::forbidden("Not a member of this organization");
+    }
+    let engine = match payload.template_engine.as_deref().map(parse_engine) {
+        Some(Ok(engine)) => engine,
+        Some(Err(e)) => return e.into(),
+        None => Engine::default(),
     };
+    let content_type = match payload.content_type.as_deref().map(parse_content_type) {
+        Some(Ok(t)) => t,
+        Some(Err(e)) => return e.into(),
+        None => ContentType::default(),
+    };
+    let prompt = Prompts::new(payload.name);
     let prompt_model = prompts::ActiveModel {
         file_key: Set(prompt.id()),
+        name: Set(prompt.name()),
         user_id: Set(Some(claims.id)),
+        org_id: Set(payload.org_id),
+        template_engine: Set(engine.as_str().to_string()),
+        content_type: Set(content_type.as_str().to_string()),
         ..Default::default()
     };
     match PromptData::insert(prompt_model).exec(&data.sql_conn).await {
-        Ok(pt) => AppResponse::ok(
-            "Create Prompt finished.".to_string(),
-            Some(CreateResponse {
-                id: pt.last_insert_id,
-            }),
-        ),
-        Err(e) => AppResponse::internal_err(format!("Failed to add prompt: {e}")),
+        Ok(pt) => {
+            info!("Prompt {} saved.", prompt.id());
+            AppResponse::ok(
+                "Create Prompt finished.".to_string(),
+                Some(CreateResponse {
+                    id: pt.last_insert_id,
+                }),
+            )
+        }
+        Err(e) => ShelfError::Db(e).into(),
+    }
+}
+
+fn parse_engine(name: &str) -> ShelfResult<Engine> {
+    Engine::parse(name).ok_or_else(|| {
+        ShelfError::Validation(format!(
+            "Unknown template engine {name}, expected simple or jinja"
+        ))
+    })
+}
+
+/// Prompts created before engines were selectable use `simple`.
+fn engine_of(prompt: &prompts::Model) -> Engine {
+    Engine::parse(&prompt.template_engine).unwrap_or_default()
+}
+
+fn content_type_of(prompt: &prompts::Model) -> ContentType {
+    ContentType::parse(&prompt.content_type).unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+pub struct EngineInfo {
+    template_engine: String,
+}
+
+pub async fn set_engine(
+    State(data): State<Arc<AppState>>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Manage, EngineInfo>,
+) -> AppResponse<String> {
+    let engine = match parse_engine(&payload.template_engine) {
+        Ok(e) => e,
+        Err(e) => return e.into(),
+    };
+    let mut prompt: prompts::ActiveModel = auth.prompt.into();
+    prompt.template_engine = Set(engine.as_str().to_string());
+    match prompt.update(&data.sql_conn).await {
+        Ok(_) => AppResponse::ok(format!("Template engine set to {engine}"), None),
+        Err(e) => ShelfError::Db(e).into(),
     }
 }
 
+pub fn cache_key(prompt_id: u64) -> String {
+    format!("prompt_{prompt_id}")
+}
+
+/// Drops the cached index of each prompt after a change outside the
+/// version/commit writers. Failures are only logged, entries expire anyway.
+pub async fn evict_prompts(pool: &deadpool_redis::Pool, prompt_ids: &[u64]) {
+    let keys: Vec<String> = prompt_ids.iter().map(|id| cache_key(*id)).collect();
+    let res = match pool.get().await {
+        Ok(mut conn) => del_cache(&keys, &mut conn).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = res {
+        error!("Failed to evict prompt cache: {e}");
+    }
+}
+
+/// Cache-through load of the version/commit index. The cache entry is shared by
+/// every member, so callers must hold an authorized `prompt` already.
 pub async fn query_prompt(
     redis_conn: &mut deadpool_redis::Connection,
     sql_conn: &DatabaseConnection,
-    user_id: i64,
-    prompt_id: u64,
-) -> Result<Prompts> {
-    let key = format!("user_{user_id}/prompt_{prompt_id}");
-    if let Ok(prompt) = get_cache(&key, redis_conn).await {
-        return serde_json::from_str(&prompt)
-            .map_err(|e| anyhow!("Failed to serialize prompt: {e}"));
+    prompt: &prompts::Model,
+) -> ShelfResult<Prompts> {
+    let key = cache_key(prompt.id);
+    if let Ok(cached) = get_cache(&key, redis_conn).await {
+        match serde_json::from_str(&cached) {
+            Ok(p) => return Ok(p),
+            // written by an older build, reload it from SQL
+            Err(e) => warn!("Ignore cached prompt {}: {e}", prompt.id),
+        }
     }
 
-    let prompt = match PromptData::find()
-        .filter(prompts::Column::Id.eq(prompt_id))
-        .filter(prompts::Column::UserId.eq(Some(user_id)))
-        .one(sql_conn)
-        .await
-    {
-        Ok(Some(p)) => p,
-        Ok(None) => return Err(anyhow!("Prompt id not exist!")),
-        Err(e) => return Err(anyhow!("Failed to query db: {}", e)),
-    };
-    let prompt_config_path = find_config(&prompt.file_key)?;
-    match Prompts::load(prompt_config_path).await {
+    match Prompts::load(sql_conn, prompt).await {
         Ok(p) => {
             if let Err(e) = set_cache(
                 &key,
@@ -109,94 +189,91 @@ pub async fn query_prompt(
     }
 }
 
+/// The commit `label` points at, with includes expanded for `user_id`.
 pub async fn query_latest_prompt(
     conn: &DatabaseConnection,
+    store: &dyn PromptStore,
     user_id: i64,
-    prompt_id: u64,
-) -> Result<PromptCommitResponse> {
-    info!("Querying latest prompt: {prompt_id}");
-    let prompt = match PromptData::find()
-        .filter(prompts::Column::Id.eq(prompt_id))
-        .filter(prompts::Column::UserId.eq(Some(user_id)))
-        .one(conn)
-        .await
-    {
-        Ok(Some(p)) => p,
-        Ok(None) => return Err(anyhow!("Prompt id not exist!")),
-        Err(e) => return Err(anyhow!("Failed to query db: {e}")),
-    };
+    prompt: prompts::Model,
+    label: Option<&str>,
+    at: Option<DateTime<Utc>>,
+) -> ShelfResult<PromptCommitResponse> {
     info!(
-        "latest version: {:?}, latest commit: {:?}",
-        prompt.latest_version, prompt.latest_commit
+        "Querying latest prompt: {}, label: {:?}, at: {:?}",
+        prompt.id, label, at
     );
-    if prompt.latest_version.is_none() || prompt.latest_commit.is_none() {
-        return Err(anyhow!("Invalid prompt commit/version "));
-    }
-    let (file_key, latest_version, latest_commit) = (
-        &prompt.file_key,
-        &prompt.latest_version.unwrap(),
-        &prompt.latest_commit.unwrap(),
+    let target = match at {
+        Some(at) => label::resolve_at(conn, &prompt, label, at).await?,
+        None => label::resolve(conn, &prompt, label).await?,
+    };
+    info!(
+        "latest version: {}, latest commit: {}",
+        target.version, target.commit_id
     );
-    let prompt_config_path = find_config(file_key)?;
-    let prompt_config = Prompts::load(prompt_config_path).await?;
+    let prompt_config = Prompts::load(conn, &prompt).await?;
     let commit = prompt_config
-        .get_commit(latest_version, latest_commit)
+        .get_commit(&target.version, &target.commit_id)
         .await?;
-    let content = Prompts::get_content(file_key, latest_version, latest_commit).await?;
-    Ok(PromptCommitResponse { commit, content })
+    let raw =
+        Prompts::get_content(store, &prompt.file_key, &target.version, &target.commit_id).await?;
+    let content = Content::parse(commit.content_type, raw).map_err(ShelfError::Validation)?;
+    let content =
+        compose::expand_content(conn, store, user_id, prompt.id, Some(&target), content).await?;
+    Ok(PromptCommitResponse {
+        version: target.version,
+        commit,
+        content,
+    })
 }
 
-pub async fn delete_prompt(conn: &DatabaseConnection, user_id: i64, prompt_id: u64) -> Result<()> {
-    let prompt = match PromptData::find_by_id(prompt_id)
-        .filter(prompts::Column::UserId.eq(user_id))
-        .one(conn)
-        .await
-    {
-        Ok(Some(p)) => p,
-        Ok(None) => return Err(anyhow!("Prompt id not exist!")),
-        Err(e) => return Err(anyhow!("Failed to query db: {}", e)),
-    };
-    Prompts::delete(&prompt.file_key).await?;
-    let _ = prompt
-        .delete(conn)
-        .await
-        .map_err(|e| anyhow!("Failed to delete prompt: {e}"));
+pub async fn delete_prompt(
+    conn: &DatabaseConnection,
+    store: &dyn PromptStore,
+    prompt: prompts::Model,
+) -> ShelfResult<()> {
+    Prompts::delete(conn, store, prompt.id, &prompt.file_key).await?;
+    prompt.delete(conn).await?;
     Ok(())
 }
 
 #[derive(Debug, Deserialize)]
 pub struct NodeInfo {
-    prompt_id: u64,
     version: String,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct NewNodeInfo {
+    version: String,
+    /// branch from this commit, which becomes the parent of the first commit
+    from_commit: Option<String>,
+}
+
 pub async fn create_node(
     State(data): State<Arc<AppState>>,
-    Extension(claims): Extension<TokenClaims>,
-    Json(payload): Json<NodeInfo>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Write, NewNodeInfo>,
 ) -> AppResponse<CreateResponse> {
     let mut redis_conn = match data.redis_pool.get().await {
         Ok(conn) => conn,
-        Err(e) => return AppResponse::internal_err(format!("Failed to get redis conn: {e}")),
+        Err(e) => return ShelfError::Cache(format!("Failed to get redis conn: {e}")).into(),
     };
-    let mut prompt_config = match query_prompt(
-        &mut redis_conn,
-        &data.sql_conn,
-        claims.id,
-        payload.prompt_id,
-    )
-    .await
+    let mut prompt_config = match query_prompt(&mut redis_conn, &data.sql_conn, &auth.prompt).await
     {
         Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to find prompt: {e}")),
+        Err(e) => return e.into(),
     };
-    if let Err(e) = prompt_config.create_version(&payload.version).await {
-        return AppResponse::internal_err(format!("Failed to create version: {e}"));
-    }
-    if let Err(e) = prompt_config.save().await {
-        return AppResponse::internal_err(format!("Failed to save prompt config: {e}"));
+    if let Err(e) = prompt_config
+        .create_version(
+            &data.sql_conn,
+            auth.prompt.id,
+            &payload.version,
+            payload.from_commit,
+        )
+        .await
+    {
+        return e.into();
     }
 
-    let key = format!("user_{}/prompt_{}", claims.id, &payload.prompt_id);
+    let key = cache_key(auth.prompt.id);
     if let Err(e) = set_cache(
         &key,
         serde_json::to_string(&prompt_config).unwrap().as_str(),
@@ -216,63 +293,150 @@ pub async fn create_node(
 
 #[derive(Debug, Deserialize)]
 pub struct CommitInfo {
-    prompt_id: u64,
     version: String,
     desp: String,
-    content: String,
+    /// a string, or a list of messages for `chat` content
+    content: Value,
+    /// defaults to `chat` for a list and to the prompt's type otherwise
+    content_type: Option<String>,
     as_latest: bool,
+    reason: Option<String>,
+    variables: Option<Vec<Variable>>,
+    model_config: Option<ModelConfig>,
+    /// JSON Schema the model output must satisfy
+    output_schema: Option<Value>,
+    /// reject instead of warn when `variables` does not match the template
+    #[serde(default)]
+    strict_variables: bool,
 }
 
 #[derive(Debug, Serialize)]
 pub struct CommitResponse {
-    commit_id: String,
+    pub commit_id: String,
+    /// mismatches between the declared variables and the template
+    pub warnings: Vec<TemplateIssue>,
 }
 
-pub async fn create_commit(
-    State(data): State<Arc<AppState>>,
-    Extension(claims): Extension<TokenClaims>,
-    Json(payload): Json<CommitInfo>,
-) -> AppResponse<CommitResponse> {
-    let mut redis_conn = match data.redis_pool.get().await {
-        Ok(conn) => conn,
-        Err(e) => return AppResponse::internal_err(format!("Failed to get redis conn: {e}")),
-    };
-    let mut prompt_config = match query_prompt(
-        &mut redis_conn,
-        &data.sql_conn,
-        claims.id,
-        payload.prompt_id,
-    )
-    .await
-    {
-        Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to find prompt: {e}")),
-    };
-    let commit = PromptCommit::new(claims.email, payload.desp);
-    if let Err(e) = prompt_config
-        .commit(&payload.version, commit.clone(), &payload.content)
-        .await
-    {
-        return AppResponse::internal_err(format!("Failed to commit prompt: {e}"));
+fn parse_content_type(name: &str) -> ShelfResult<ContentType> {
+    ContentType::parse(name).ok_or_else(|| {
+        ShelfError::Validation(format!(
+            "Unknown content type {name}, expected plain, markdown, json, yaml or chat"
+        ))
+    })
+}
+
+fn join_issues(issues: &[TemplateIssue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Variables `content` uses but `declared` lacks and the other way round. An
+/// invalid schema is always rejected, mismatches only when `strict`.
+fn check_variables(
+    engine: Engine,
+    content: &Content,
+    declared: &[Variable],
+    strict: bool,
+) -> ShelfResult<Vec<TemplateIssue>> {
+    let issues = schema::check(declared);
+    if !issues.is_empty() {
+        return Err(ShelfError::Validation(format!(
+            "Invalid variable schema: {}",
+            join_issues(&issues)
+        )));
     }
-    if let Err(e) = prompt_config.save().await {
-        return AppResponse::internal_err(format!("Failed to save prompt config: {e}"));
+    let used = template::variables(engine, &content.parts())
+        .map_err(|issue| ShelfError::Validation(format!("Template does not compile: {issue}")))?;
+    let issues = schema::declaration_issues(declared, &used);
+    if strict && !issues.is_empty() {
+        return Err(ShelfError::Validation(format!(
+            "Variables do not match the template: {}",
+            join_issues(&issues)
+        )));
     }
-    if payload.as_latest {
-        if let Err(e) = PromptData::update(prompts::ActiveModel {
-            id: Set(payload.prompt_id),
-            latest_version: Set(Some(payload.version.clone())),
-            latest_commit: Set(Some(commit.commit_id.clone())),
-            ..Default::default()
-        })
-        .exec(&data.sql_conn)
+    Ok(issues)
+}
+
+/// Expands the includes of `content` and compiles every part with the engine
+/// of `prompt`, then compares the parts with `declared` variables.
+pub async fn check_content(
+    conn: &DatabaseConnection,
+    store: &dyn PromptStore,
+    user_id: i64,
+    prompt: &prompts::Model,
+    content: &Content,
+    declared: Option<&[Variable]>,
+    strict: bool,
+) -> ShelfResult<Vec<TemplateIssue>> {
+    let expanded =
+        compose::expand_content(conn, store, user_id, prompt.id, None, content.clone()).await?;
+    let engine = engine_of(prompt);
+    for part in expanded.parts() {
+        template::check(engine, part).map_err(|issue| {
+            ShelfError::Validation(format!("Template does not compile: {issue}"))
+        })?;
+    }
+    match declared {
+        Some(declared) => check_variables(engine, &expanded, declared, strict),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Writes `commit` with `content` into `version` and refreshes the cached
+/// index. With `as_latest` the `latest` label moves to it.
+pub async fn save_commit(
+    data: &AppState,
+    prompt: prompts::Model,
+    version: &str,
+    commit: &PromptCommit,
+    content: &Content,
+    as_latest: bool,
+    reason: Option<String>,
+) -> ShelfResult<()> {
+    let mut redis_conn = data
+        .redis_pool
+        .get()
         .await
-        {
-            return AppResponse::internal_err(format!("Failed to update prompt version: {e}"));
-        }
+        .map_err(|e| ShelfError::Cache(format!("Failed to get redis conn: {e}")))?;
+    let mut prompt_config = query_prompt(&mut redis_conn, &data.sql_conn, &prompt).await?;
+    prompt_config
+        .commit(
+            &data.sql_conn,
+            data.store.as_ref(),
+            prompt.id,
+            version,
+            commit.clone(),
+            &content.to_raw(),
+        )
+        .await?;
+    compose::record_includes(
+        &data.sql_conn,
+        prompt.id,
+        version,
+        &commit.commit_id,
+        content,
+    )
+    .await?;
+    let key = cache_key(prompt.id);
+    if as_latest {
+        let target = LabelTarget {
+            version: version.to_string(),
+            commit_id: commit.commit_id.clone(),
+        };
+        label::move_label(
+            &data.sql_conn,
+            prompt,
+            DEFAULT_LABEL,
+            target,
+            &commit.author,
+            reason,
+        )
+        .await?;
     }
 
-    let key = format!("user_{}/prompt_{}", claims.id, &payload.prompt_id);
     if let Err(e) = set_cache(
         &key,
         serde_json::to_string(&prompt_config).unwrap().as_str(),
@@ -283,18 +447,84 @@ pub async fn create_commit(
     {
         error!("Failed to set key/value: {e}");
     };
+    Ok(())
+}
 
-    AppResponse::ok(
+pub async fn create_commit(
+    State(data): State<Arc<AppState>>,
+    Extension(claims): Extension<TokenClaims>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Write, CommitInfo>,
+) -> Result<AppResponse<CommitResponse>, AppResponse<ContentError>> {
+    // moving the latest pointer is a maintainer action
+    if payload.as_latest && auth.role < PromptRole::Maintainer {
+        return Err(role_denied(PromptRole::Maintainer, auth.role).into());
+    }
+    let content_type = payload
+        .content_type
+        .as_deref()
+        .map(parse_content_type)
+        .transpose()?;
+    let (content_type, content) =
+        Content::from_value(content_type, content_type_of(&auth.prompt), payload.content).map_err(
+            |e| {
+                AppResponse::new(
+                    AppCode::UnprocessableEntity,
+                    "Invalid content".to_string(),
+                    Some(e),
+                )
+            },
+        )?;
+    if let Some(config) = &payload.model_config
+        && let Err(e) = config.validate()
+    {
+        return Err(ShelfError::Validation(format!("Invalid model_config: {e}")).into());
+    }
+    if let Some(schema) = &payload.output_schema
+        && let Err(e) = output_schema::check(schema)
+    {
+        return Err(ShelfError::Validation(format!("Invalid output_schema: {e}")).into());
+    }
+    // reject broken includes and templates that do not compile before anything
+    // is written
+    let warnings = check_content(
+        &data.sql_conn,
+        data.store.as_ref(),
+        claims.id,
+        &auth.prompt,
+        &content,
+        payload.variables.as_deref(),
+        payload.strict_variables,
+    )
+    .await?;
+    let mut commit = PromptCommit::new(claims.email, payload.desp);
+    commit.content_type = content_type;
+    commit.variables = payload.variables;
+    commit.model_config = payload.model_config;
+    commit.output_schema = payload.output_schema;
+    save_commit(
+        &data,
+        auth.prompt,
+        &payload.version,
+        &commit,
+        &content,
+        payload.as_latest,
+        payload.reason,
+    )
+    .await?;
+
+    Ok(AppResponse::ok(
         "Create commit finished".to_string(),
         Some(CommitResponse {
             commit_id: commit.commit_id,
+            warnings,
         }),
-    )
+    ))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct QueryParams {
     id: Option<u64>,
+    name: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -306,6 +536,8 @@ pub struct PromptResponse {
     updated_at: DateTime<Utc>,
     user_id: Option<i64>,
     org_id: Option<i64>,
+    template_engine: String,
+    content_type: String,
     prompt: Prompts,
 }
 
@@ -318,252 +550,413 @@ pub async fn query(
     if let Some(prompt_id) = params.id {
         filter_condition = filter_condition.add(prompts::Column::Id.eq(prompt_id));
     }
+    if let Some(name) = params.name {
+        filter_condition = filter_condition.add(prompts::Column::Name.contains(name));
+    }
     let prompt_list = match PromptData::find()
         .filter(filter_condition)
-        .filter(prompts::Column::UserId.eq(Some(claims.id)))
+        .filter(visible_to(claims.id))
         .all(&data.sql_conn)
         .await
     {
         Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to query db: {e}")),
+        Err(e) => return ShelfError::Db(e).into(),
     };
 
-    let res: Vec<PromptResponse> = stream::iter(prompt_list)
-        .map(|p| async move {
-            let prompt_config_path = match find_config(&p.file_key) {
-                Ok(p) => p,
-                Err(e) => {
-                    error!("Failed to find prompt: {e}");
-                    return None;
-                }
-            };
-            let prompt = match Prompts::load(prompt_config_path).await {
-                Ok(p) => p,
-                Err(e) => {
-                    error!("Failed to load prompt: {e}");
-                    return None;
-                }
-            };
+    let mut prompt_configs = match Prompts::load_many(&data.sql_conn, &prompt_list).await {
+        Ok(p) => p,
+        Err(e) => return e.into(),
+    };
+    let res: Vec<PromptResponse> = prompt_list
+        .into_iter()
+        .filter_map(|p| {
+            let prompt = prompt_configs.remove(&p.id)?;
             Some(PromptResponse {
                 id: p.id,
-                latest_version: p.latest_version.clone(),
-                latest_commit: p.latest_commit.clone(),
+                latest_version: p.latest_version,
+                latest_commit: p.latest_commit,
                 created_at: p.created_at,
                 updated_at: p.updated_at,
                 user_id: p.user_id,
                 org_id: p.org_id,
+                template_engine: p.template_engine,
+                content_type: p.content_type,
                 prompt,
             })
         })
-        .buffer_unordered(MAX_CONCURRENT_TASKS)
-        .filter_map(|p| async move { p })
-        .collect()
-        .await;
+        .collect();
 
     AppResponse::ok("Query prompt finished".to_string(), Some(res))
 }
 
 #[derive(Serialize)]
 pub struct PromptCommitResponse {
+    version: String,
     commit: PromptCommit,
-    content: String,
+    content: Content,
+}
+
+#[derive(Deserialize)]
+pub struct LabelParams {
+    label: Option<String>,
+    /// resolve the commit that was live at this instant instead of now
+    at: Option<DateTime<Utc>>,
 }
 
 pub async fn latest(
     State(data): State<Arc<AppState>>,
     Extension(claims): Extension<TokenClaims>,
-    Query(params): Query<CreateResponse>,
+    auth: AuthorizedPrompt<Read>,
+    Query(params): Query<LabelParams>,
 ) -> AppResponse<PromptCommitResponse> {
-    match query_latest_prompt(&data.sql_conn, claims.id, params.id).await {
+    match query_latest_prompt(
+        &data.sql_conn,
+        data.store.as_ref(),
+        claims.id,
+        auth.prompt,
+        params.label.as_deref(),
+        params.at,
+    )
+    .await
+    {
         Ok(c) => AppResponse::ok("Query successfully".to_string(), Some(c)),
-        Err(e) => AppResponse::internal_err(format!("Query failed: {e}")),
+        Err(e) => e.into(),
     }
 }
 
 #[derive(Deserialize)]
 pub struct ContentQueryParams {
-    prompt_id: u64,
+    version: Option<String>,
+    commit_id: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ContentResponse {
     version: String,
     commit_id: String,
+    content_type: ContentType,
+    content: Content,
+    variables: Option<Vec<Variable>>,
+    model_config: Option<ModelConfig>,
+    output_schema: Option<Value>,
 }
 
 pub async fn query_content(
     State(data): State<Arc<AppState>>,
-    Extension(claims): Extension<TokenClaims>,
+    auth: AuthorizedPrompt<Read>,
     Query(params): Query<ContentQueryParams>,
-) -> AppResponse<String> {
-    let mut redis_conn = match data.redis_pool.get().await {
-        Ok(conn) => conn,
-        Err(e) => return AppResponse::internal_err(format!("Failed to get redis conn: {e}")),
+) -> AppResponse<ContentResponse> {
+    let target = match label::resolve_target(
+        &data.sql_conn,
+        &auth.prompt,
+        params.version,
+        params.commit_id,
+        params.label.as_deref(),
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => return e.into(),
     };
-    let prompt_config =
-        match query_prompt(&mut redis_conn, &data.sql_conn, claims.id, params.prompt_id).await {
-            Ok(p) => p,
-            Err(e) => return AppResponse::internal_err(format!("Failed to find prompt: {e}")),
-        };
-    let content =
-        match Prompts::get_content(&prompt_config.id(), &params.version, &params.commit_id).await {
+    let commit = match Prompts::load(&data.sql_conn, &auth.prompt).await {
+        Ok(p) => match p.get_commit(&target.version, &target.commit_id).await {
             Ok(c) => c,
-            Err(e) => {
-                return AppResponse::internal_err(format!("Failed to get prompt content: {e}"));
-            }
-        };
-    AppResponse::ok("Query content finished".to_string(), Some(content))
+            Err(e) => return e.into(),
+        },
+        Err(e) => return e.into(),
+    };
+    let content = match Prompts::get_content(
+        data.store.as_ref(),
+        &auth.prompt.file_key,
+        &target.version,
+        &target.commit_id,
+    )
+    .await
+    .and_then(|raw| Content::parse(commit.content_type, raw).map_err(ShelfError::Validation))
+    {
+        Ok(c) => c,
+        Err(e) => return e.into(),
+    };
+    AppResponse::ok(
+        "Query content finished".to_string(),
+        Some(ContentResponse {
+            version: target.version,
+            commit_id: target.commit_id,
+            content_type: commit.content_type,
+            content,
+            variables: commit.variables,
+            model_config: commit.model_config,
+            output_schema: commit.output_schema,
+        }),
+    )
 }
 
-pub async fn del(
+#[derive(Deserialize)]
+pub struct RenderInfo {
+    version: Option<String>,
+    commit_id: Option<String>,
+    label: Option<String>,
+    #[serde(default)]
+    variables: Map<String, Value>,
+}
+
+#[derive(Serialize)]
+pub struct RenderResponse {
+    version: String,
+    commit_id: String,
+    content: Content,
+}
+
+pub async fn render(
     State(data): State<Arc<AppState>>,
     Extension(claims): Extension<TokenClaims>,
-    Query(params): Query<QueryParams>,
-) -> AppResponse<CreateResponse> {
-    if let Some(id) = params.id {
-        match delete_prompt(&data.sql_conn, claims.id, id).await {
-            Ok(()) => AppResponse::ok(
-                "prompt has been deleted".to_string(),
-                Some(CreateResponse { id }),
-            ),
-            Err(e) => AppResponse::internal_err(format!("failed to delete prompt: {e}")),
+    AuthorizedJson { auth, payload }: AuthorizedJson<Read, RenderInfo>,
+) -> Result<AppResponse<RenderResponse>, AppResponse<Vec<TemplateIssue>>> {
+    let engine = engine_of(&auth.prompt);
+    let target = label::resolve_target(
+        &data.sql_conn,
+        &auth.prompt,
+        payload.version,
+        payload.commit_id,
+        payload.label.as_deref(),
+    )
+    .await?;
+    let commit = Prompts::load(&data.sql_conn, &auth.prompt)
+        .await?
+        .get_commit(&target.version, &target.commit_id)
+        .await?;
+    let raw = Prompts::get_content(
+        data.store.as_ref(),
+        &auth.prompt.file_key,
+        &target.version,
+        &target.commit_id,
+    )
+    .await?;
+    let content = Content::parse(commit.content_type, raw).map_err(ShelfError::Validation)?;
+    let content = compose::expand_content(
+        &data.sql_conn,
+        data.store.as_ref(),
+        claims.id,
+        auth.prompt.id,
+        Some(&target),
+        content,
+    )
+    .await?;
+    // declared variables get their defaults, those the template does not use
+    // are dropped
+    let vars = match commit.variables {
+        Some(declared) => {
+            let used = template::variables(engine, &content.parts())
+                .map_err(|i| render_failed(vec![i]))?;
+            let mut vars =
+                schema::bind(&declared, &used, &payload.variables).map_err(render_failed)?;
+            vars.retain(|name, _| used.contains(name));
+            vars
         }
-    } else {
-        AppResponse::bad_request("prompt id is null")
+        None => payload.variables,
+    };
+    match template::render_parts(engine, &content.parts(), &vars) {
+        Ok(parts) => Ok(AppResponse::ok(
+            "Render finished".to_string(),
+            Some(RenderResponse {
+                version: target.version,
+                commit_id: target.commit_id,
+                content: content.with_parts(parts),
+            }),
+        )),
+        Err(issues) => Err(render_failed(issues)),
+    }
+}
+
+fn render_failed(issues: Vec<TemplateIssue>) -> AppResponse<Vec<TemplateIssue>> {
+    AppResponse::new(
+        AppCode::UnprocessableEntity,
+        "Failed to render template".to_string(),
+        Some(issues),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct OutputInfo {
+    version: Option<String>,
+    commit_id: Option<String>,
+    label: Option<String>,
+    /// the raw model output, parsed as JSON
+    output: String,
+}
+
+#[derive(Serialize)]
+pub struct OutputValidation {
+    version: String,
+    commit_id: String,
+    valid: bool,
+    errors: Vec<OutputIssue>,
+}
+
+/// Checks a model output against the output schema of a commit (default:
+/// `latest` or `label`).
+pub async fn validate_output(
+    State(data): State<Arc<AppState>>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Read, OutputInfo>,
+) -> AppResponse<OutputValidation> {
+    let target = match label::resolve_target(
+        &data.sql_conn,
+        &auth.prompt,
+        payload.version,
+        payload.commit_id,
+        payload.label.as_deref(),
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => return e.into(),
+    };
+    let commit = match Prompts::load(&data.sql_conn, &auth.prompt).await {
+        Ok(p) => match p.get_commit(&target.version, &target.commit_id).await {
+            Ok(c) => c,
+            Err(e) => return e.into(),
+        },
+        Err(e) => return e.into(),
+    };
+    let Some(schema) = commit.output_schema else {
+        return ShelfError::NotFound(format!("Commit {} has no output schema", target.commit_id))
+            .into();
+    };
+    let errors = match output_schema::validate(&schema, &payload.output) {
+        Ok(errors) => errors,
+        Err(e) => return ShelfError::Validation(format!("Invalid output_schema: {e}")).into(),
+    };
+    AppResponse::ok(
+        "Validate output finished".to_string(),
+        Some(OutputValidation {
+            version: target.version,
+            commit_id: target.commit_id,
+            valid: errors.is_empty(),
+            errors,
+        }),
+    )
+}
+
+pub async fn del(
+    State(data): State<Arc<AppState>>,
+    auth: AuthorizedPrompt<Own>,
+) -> AppResponse<CreateResponse> {
+    let id = auth.prompt.id;
+    let res = delete_prompt(&data.sql_conn, data.store.as_ref(), auth.prompt).await;
+    evict_prompts(&data.redis_pool, &[id]).await;
+    match res {
+        Ok(()) => AppResponse::ok(
+            "prompt has been deleted".to_string(),
+            Some(CreateResponse { id }),
+        ),
+        Err(e) => e.into(),
     }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RollbackInfo {
-    prompt_id: u64,
     version: String,
     commit_id: String,
+    label: Option<String>,
+    reason: Option<String>,
 }
 
 pub async fn rollback(
     State(data): State<Arc<AppState>>,
     Extension(claims): Extension<TokenClaims>,
-    Json(payload): Json<RollbackInfo>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Manage, RollbackInfo>,
 ) -> AppResponse<CreateResponse> {
-    let mut redis_conn = match data.redis_pool.get().await {
-        Ok(conn) => conn,
-        Err(e) => return AppResponse::internal_err(format!("Failed to get redis conn: {e}")),
-    };
-    let prompt_config = match query_prompt(
-        &mut redis_conn,
-        &data.sql_conn,
-        claims.id,
-        payload.prompt_id,
-    )
-    .await
-    {
+    let prompt_config = match Prompts::load(&data.sql_conn, &auth.prompt).await {
         Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to find prompt: {e}")),
+        Err(e) => return e.into(),
     };
     if let Err(e) = prompt_config
         .get_commit(&payload.version, &payload.commit_id)
         .await
     {
-        return AppResponse::internal_err(format!(
-            "Commit not found for prompt_id={}, version={}, commit_id={}, err={}",
-            payload.prompt_id, payload.version, payload.commit_id, e
-        ));
+        return e.into();
     }
-    if let Err(e) = PromptData::update(prompts::ActiveModel {
-        id: Set(payload.prompt_id),
-        latest_version: Set(Some(payload.version.clone())),
-        latest_commit: Set(Some(payload.commit_id.clone())),
-        ..Default::default()
-    })
-    .exec(&data.sql_conn)
+    let id = auth.prompt.id;
+    let name = payload.label.as_deref().unwrap_or(DEFAULT_LABEL);
+    let target = LabelTarget {
+        version: payload.version,
+        commit_id: payload.commit_id,
+    };
+    if let Err(e) = label::move_label(
+        &data.sql_conn,
+        auth.prompt,
+        name,
+        target,
+        &claims.email,
+        payload.reason,
+    )
     .await
     {
-        return AppResponse::internal_err(format!("Update failed: {e}"));
+        return e.into();
     }
-    AppResponse::ok(
-        "Rollback successful".into(),
-        Some(CreateResponse {
-            id: payload.prompt_id,
-        }),
-    )
+    AppResponse::ok("Rollback successful".into(), Some(CreateResponse { id }))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RevertInfo {
-    prompt_id: u64,
+    label: Option<String>,
+    reason: Option<String>,
 }
 
 pub async fn revert(
     State(data): State<Arc<AppState>>,
     Extension(claims): Extension<TokenClaims>,
-    Json(payload): Json<RevertInfo>,
+    AuthorizedJson { auth, payload }: AuthorizedJson<Manage, RevertInfo>,
 ) -> AppResponse<CreateResponse> {
-    let prompt = match PromptData::find()
-        .filter(prompts::Column::Id.eq(payload.prompt_id))
-        .filter(prompts::Column::UserId.eq(Some(claims.id)))
-        .one(&data.sql_conn)
-        .await
-    {
-        Ok(Some(p)) => p,
-        Ok(None) => return AppResponse::bad_request("Prompt id not exist!"),
-        Err(e) => return AppResponse::internal_err(format!("Failed to query db: {e}")),
-    };
-    let prompt_config_path = match find_config(&prompt.file_key) {
+    let prompt_config = match Prompts::load(&data.sql_conn, &auth.prompt).await {
         Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to find path: {e}")),
+        Err(e) => return e.into(),
     };
-
-    let prompt_config = match Prompts::load(prompt_config_path).await {
-        Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Config not found: {e}")),
+    let name = payload.label.as_deref().unwrap_or(DEFAULT_LABEL);
+    let current = match label::resolve(&data.sql_conn, &auth.prompt, Some(name)).await {
+        Ok(t) => t,
+        Err(e) => return e.into(),
     };
-    if prompt.latest_version.is_none() || prompt.latest_commit.is_none() {
-        return AppResponse::bad_request("Invalid prompt commit/version ");
-    }
 
     let prev_cid = match prompt_config
-        .prev_commit(
-            &prompt.latest_version.unwrap(),
-            &prompt.latest_commit.unwrap(),
-        )
+        .prev_commit(&current.version, &current.commit_id)
         .await
     {
         Ok(cid) => cid,
-        Err(e) => return AppResponse::internal_err(format!("Prev commit not found: {e}")),
+        Err(e) => return e.into(),
     };
-    if let Err(e) = PromptData::update(prompts::ActiveModel {
-        id: Set(payload.prompt_id),
-        latest_commit: Set(Some(prev_cid.clone())),
-        ..Default::default()
-    })
-    .exec(&data.sql_conn)
+    let id = auth.prompt.id;
+    let target = LabelTarget {
+        version: current.version,
+        commit_id: prev_cid,
+    };
+    if let Err(e) = label::move_label(
+        &data.sql_conn,
+        auth.prompt,
+        name,
+        target,
+        &claims.email,
+        payload.reason,
+    )
     .await
     {
-        return AppResponse::internal_err(format!("Update failed: {e}"));
+        return e.into();
     }
-    AppResponse::ok(
-        "Revert successful".into(),
-        Some(CreateResponse {
-            id: payload.prompt_id,
-        }),
-    )
+    AppResponse::ok("Revert successful".into(), Some(CreateResponse { id }))
 }
 
 pub async fn list_version(
     State(data): State<Arc<AppState>>,
-    Extension(claims): Extension<TokenClaims>,
-    Query(payload): Query<RevertInfo>,
+    auth: AuthorizedPrompt<Read>,
 ) -> AppResponse<Vec<String>> {
     let mut redis_conn = match data.redis_pool.get().await {
         Ok(conn) => conn,
-        Err(e) => return AppResponse::internal_err(format!("Failed to get redis conn: {e}")),
+        Err(e) => return ShelfError::Cache(format!("Failed to get redis conn: {e}")).into(),
     };
-    let prompt_config = match query_prompt(
-        &mut redis_conn,
-        &data.sql_conn,
-        claims.id,
-        payload.prompt_id,
-    )
-    .await
-    {
+    let prompt_config = match query_prompt(&mut redis_conn, &data.sql_conn, &auth.prompt).await {
         Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to find prompt: {e}")),
+        Err(e) => return e.into(),
     };
     let vers = prompt_config.list_version();
     AppResponse::ok("List version finished".to_string(), Some(vers))
@@ -571,68 +964,120 @@ pub async fn list_version(
 
 pub async fn list_commits(
     State(data): State<Arc<AppState>>,
-    Extension(claims): Extension<TokenClaims>,
+    auth: AuthorizedPrompt<Read>,
     Query(payload): Query<NodeInfo>,
 ) -> AppResponse<Vec<String>> {
     let mut redis_conn = match data.redis_pool.get().await {
         Ok(conn) => conn,
-        Err(e) => return AppResponse::internal_err(format!("Failed to get redis conn: {e}")),
+        Err(e) => return ShelfError::Cache(format!("Failed to get redis conn: {e}")).into(),
     };
-    let prompt_config = match query_prompt(
-        &mut redis_conn,
-        &data.sql_conn,
-        claims.id,
-        payload.prompt_id,
-    )
-    .await
-    {
+    let prompt_config = match query_prompt(&mut redis_conn, &data.sql_conn, &auth.prompt).await {
         Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to find prompt: {e}")),
+        Err(e) => return e.into(),
     };
     let commits = prompt_config.list_commits(&payload.version);
     AppResponse::ok("List commits finished".to_string(), Some(commits))
 }
 
+/// One side of a diff. Without `version` and `commit_id` the commit `label`
+/// (default `latest`) points at is used.
+#[derive(Deserialize, Default)]
+pub struct CommitRef {
+    /// the `prompt_id` of the request when absent
+    prompt_id: Option<u64>,
+    version: Option<String>,
+    commit_id: Option<String>,
+    label: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct DiffParam {
-    prompt_id: u64,
-    left_version: String,
-    right_version: String,
-    left_commit: String,
-    right_commit: String,
+    prompt_id: Option<u64>,
+    #[serde(default)]
+    left: CommitRef,
+    #[serde(default)]
+    right: CommitRef,
+    left_version: Option<String>,
+    right_version: Option<String>,
+    left_commit: Option<String>,
+    right_commit: Option<String>,
+    #[serde(flatten)]
+    options: DiffOptions,
 }
 
+/// Authorizes read access to the prompt of `side` and resolves its commit.
+async fn resolve_side(
+    data: &AppState,
+    redis_conn: &mut deadpool_redis::Connection,
+    user_id: i64,
+    prompt_id: Option<u64>,
+    side: CommitRef,
+) -> Result<(Prompts, LabelTarget), AppResponse<DiffResponse>> {
+    let Some(prompt_id) = side.prompt_id.or(prompt_id) else {
+        return Err(AppResponse::bad_request("prompt_id is required"));
+    };
+    let (prompt, _) =
+        access::authorize(&data.sql_conn, user_id, prompt_id, PromptRole::Viewer).await?;
+    let target = label::resolve_target(
+        &data.sql_conn,
+        &prompt,
+        side.version,
+        side.commit_id,
+        side.label.as_deref(),
+    )
+    .await?;
+    let config = query_prompt(redis_conn, &data.sql_conn, &prompt).await?;
+    config
+        .get_commit(&target.version, &target.commit_id)
+        .await?;
+    Ok((config, target))
+}
+
+/// Diffs two commits, of the same prompt or of two prompts the user can read.
 pub async fn diff(
     State(data): State<Arc<AppState>>,
     Extension(claims): Extension<TokenClaims>,
     Json(payload): Json<DiffParam>,
-) -> AppResponse<String> {
+) -> AppResponse<DiffResponse> {
+    if let Err(e) = payload.options.check() {
+        return ShelfError::Validation(e).into();
+    }
     let mut redis_conn = match data.redis_pool.get().await {
         Ok(conn) => conn,
-        Err(e) => return AppResponse::internal_err(format!("Failed to get redis conn: {e}")),
+        Err(e) => return ShelfError::Cache(format!("Failed to get redis conn: {e}")).into(),
     };
-    let prompt_config = match query_prompt(
-        &mut redis_conn,
-        &data.sql_conn,
-        claims.id,
-        payload.prompt_id,
-    )
-    .await
-    {
-        Ok(p) => p,
-        Err(e) => return AppResponse::internal_err(format!("Failed to find prompt: {e}")),
-    };
-    match prompt_config
+    let DiffParam {
+        prompt_id,
+        mut left,
+        mut right,
+        ..
+    } = payload;
+    left.version = left.version.or(payload.left_version);
+    left.commit_id = left.commit_id.or(payload.left_commit);
+    right.version = right.version.or(payload.right_version);
+    right.commit_id = right.commit_id.or(payload.right_commit);
+    let (left_config, left) =
+        match resolve_side(&data, &mut redis_conn, claims.id, prompt_id, left).await {
+            Ok(side) => side,
+            Err(e) => return e,
+        };
+    let (right_config, right) =
+        match resolve_side(&data, &mut redis_conn, claims.id, prompt_id, right).await {
+            Ok(side) => side,
+            Err(e) => return e,
+        };
+    match left_config
         .diff_content(
-            &payload.left_version,
-            &payload.right_version,
-            &payload.left_commit,
-            &payload.right_commit,
+            data.store.as_ref(),
+            &left,
+            &right_config,
+            &right,
+            &payload.options,
         )
         .await
     {
         Ok(p) => AppResponse::ok("Diff content finished".to_string(), Some(p)),
-        Err(e) => AppResponse::internal_err(format!("Failed to diff prompt: {e}")),
+        Err(e) => e.into(),
     }
 }
 
@@ -647,11 +1092,27 @@ pub fn routes(app_state: Arc<AppState>) -> Router {
         .route("/query", get(query))
         .route("/latest", get(latest))
         .route("/content", get(query_content))
+        .route("/render", post(render))
+        .route("/validate_output", post(validate_output))
+        .route("/included_by", get(compose::included_by))
+        .route("/template_engine", post(set_engine))
+        .route("/merge", post(history::merge))
+        .route("/cherry_pick", post(history::cherry_pick))
+        .route("/revert_commit", post(history::revert_commit))
+        .route("/apply_patch", post(history::apply_patch))
+        .route("/blame", get(history::blame))
         .route("/rollback", post(rollback))
         .route("/revert", post(revert))
         .route("/list_version", get(list_version))
         .route("/list_commit", get(list_commits))
         .route("/diff", post(diff))
+        .route("/label/set", post(label::set_label))
+        .route("/label/list", get(label::list_label))
+        .route("/label/delete", post(label::delete_label))
+        .route("/label/history", get(label::label_history))
+        .route("/permission/grant", post(access::grant))
+        .route("/permission/list", get(access::list_permission))
+        .route("/permission/revoke", post(access::revoke))
         .route("/", delete(del))
         .layer(ValidateRequestHeaderLayer::custom(jwt_auth))
         .with_state(app_state)
@@ -0,0 +1,167 @@
+use axum::http::{Method, StatusCode};
+use sea_orm::{EntityTrait, PaginatorTrait};
+use serde_json::json;
+
+use super::TestApp;
+use crate::db::prelude::PromptPermissions;
+
+async fn grants(app: &TestApp) -> u64 {
+    PromptPermissions::find()
+        .count(&app.state.sql_conn)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn listing_grants_needs_owner() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = app
+        .post(
+            "/prompt/permission/grant",
+            json!({ "prompt_id": id, "user_id": 2, "role": "maintainer" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+
+    let uri = format!("/prompt/permission/list?prompt_id={id}");
+    let (status, _) = app.call_as(2, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, res) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"][0]["subject_id"], 2);
+}
+
+#[tokio::test]
+async fn deleting_a_prompt_drops_its_grants() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let id = app.prompt_with_version("greeting").await;
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": id, "user_id": 2, "role": "viewer" }),
+    )
+    .await;
+    assert_eq!(grants(&app).await, 1);
+
+    let (status, res) = app
+        .call_as(1, Method::DELETE, &format!("/prompt?prompt_id={id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(grants(&app).await, 0);
+}
+
+#[tokio::test]
+async fn deleting_an_org_drops_its_grants() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = app.post("/org/create", json!({ "name": "team" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let org_id = res["result"]["id"].as_i64().unwrap();
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": id, "org_id": org_id, "role": "editor" }),
+    )
+    .await;
+    assert_eq!(grants(&app).await, 1);
+
+    let (status, res) = app
+        .call_as(1, Method::DELETE, &format!("/org/{org_id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(grants(&app).await, 0);
+}
+
+/// Commits and rollbacks by user 2, who must be refused at viewer.
+async fn assert_viewer_refused(app: &TestApp, id: u64, commit_id: &str) {
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/create_commit",
+            Some(json!({
+                "prompt_id": id,
+                "version": "v1",
+                "desp": "edit",
+                "content": "Bye\n",
+                "as_latest": false,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{res}");
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/rollback",
+            Some(json!({ "prompt_id": id, "version": "v1", "commit_id": commit_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{res}");
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::GET,
+            &format!("/prompt/latest?prompt_id={id}"),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+}
+
+#[tokio::test]
+async fn viewers_cannot_commit_or_roll_back() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let id = app.prompt_with_version("greeting").await;
+    let commit_id = app.commit(id, json!("Hello\n")).await;
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": id, "user_id": 2, "role": "viewer" }),
+    )
+    .await;
+    assert_viewer_refused(&app, id, &commit_id).await;
+}
+
+#[tokio::test]
+async fn user_grant_overrides_org_membership() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let (status, res) = app.post("/org/create", json!({ "name": "team" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let org_id = res["result"]["id"].as_i64().unwrap();
+    app.post("/org/member/add", json!({ "org_id": org_id, "user_id": 2 }))
+        .await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_prompt",
+            json!({ "name": "shared", "org_id": org_id }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let id = res["result"]["id"].as_u64().unwrap();
+    app.post(
+        "/prompt/create_node",
+        json!({ "prompt_id": id, "version": "v1" }),
+    )
+    .await;
+    let commit_id = app.commit(id, json!("Hello\n")).await;
+
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/rollback",
+            Some(json!({ "prompt_id": id, "version": "v1", "commit_id": commit_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": id, "user_id": 2, "role": "viewer" }),
+    )
+    .await;
+    assert_viewer_refused(&app, id, &commit_id).await;
+}
@@ -0,0 +1,91 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use super::TestApp;
+use crate::{
+    init::{get_cache, set_cache},
+    routes::prompt::cache_key,
+};
+
+async fn cached(app: &TestApp, prompt_id: u64) -> Option<String> {
+    let mut conn = app.state.redis_pool.get().await.unwrap();
+    get_cache(&cache_key(prompt_id), &mut conn).await.ok()
+}
+
+#[tokio::test]
+async fn unreadable_cache_entry_is_reloaded() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let mut conn = app.state.redis_pool.get().await.unwrap();
+    set_cache(&cache_key(id), "{\"stale\":", None, &mut conn)
+        .await
+        .unwrap();
+
+    let (status, res) = app
+        .get(&format!("/prompt/list_version?prompt_id={id}"))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"], json!(["v1"]));
+}
+
+#[tokio::test]
+async fn grant_revoke_and_delete_evict_the_cache() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let id = app.prompt_with_version("greeting").await;
+    assert!(cached(&app, id).await.is_some());
+
+    let (status, res) = app
+        .post(
+            "/prompt/permission/grant",
+            json!({ "prompt_id": id, "user_id": 2, "role": "viewer" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert!(cached(&app, id).await.is_none());
+
+    app.get(&format!("/prompt/list_version?prompt_id={id}"))
+        .await;
+    assert!(cached(&app, id).await.is_some());
+    let (status, res) = app
+        .post(
+            "/prompt/permission/revoke",
+            json!({ "prompt_id": id, "user_id": 2 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert!(cached(&app, id).await.is_none());
+
+    app.get(&format!("/prompt/list_version?prompt_id={id}"))
+        .await;
+    let (status, res) = app
+        .call_as(1, Method::DELETE, &format!("/prompt?prompt_id={id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert!(cached(&app, id).await.is_none());
+}
+
+#[tokio::test]
+async fn deleting_an_org_evicts_its_prompts() {
+    let app = TestApp::new().await;
+    let (status, res) = app.post("/org/create", json!({ "name": "team" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let org_id = res["result"]["id"].as_i64().unwrap();
+    let (status, res) = app
+        .post(
+            "/prompt/create_prompt",
+            json!({ "name": "shared", "org_id": org_id }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let id = res["result"]["id"].as_u64().unwrap();
+    app.get(&format!("/prompt/list_version?prompt_id={id}"))
+        .await;
+    assert!(cached(&app, id).await.is_some());
+
+    let (status, res) = app
+        .call_as(1, Method::DELETE, &format!("/org/{org_id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert!(cached(&app, id).await.is_none());
+}
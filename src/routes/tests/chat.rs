@@ -0,0 +1,96 @@
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use super::TestApp;
+
+fn chat(user: &str) -> Value {
+    json!([
+        { "role": "system", "content": "You greet {{ name }}." },
+        { "role": "user", "content": user },
+    ])
+}
+
+#[tokio::test]
+async fn chat_commits_come_back_as_messages() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("chat").await;
+    app.commit(id, chat("Hello")).await;
+
+    let (status, res) = app.get(&format!("/prompt/latest?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], chat("Hello"));
+    assert_eq!(res["result"]["commit"]["content_type"], "chat");
+
+    let (status, res) = app
+        .post(
+            "/prompt/create_commit",
+            json!({
+                "prompt_id": id,
+                "version": "v1",
+                "desp": "edit",
+                "content": [{ "role": "narrator", "content": "Hi" }],
+                "as_latest": true,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+}
+
+#[tokio::test]
+async fn chat_render_fills_every_message() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("chat").await;
+    app.commit(id, chat("I am {{ name }}")).await;
+
+    let render = |variables| {
+        app.post(
+            "/prompt/render",
+            json!({ "prompt_id": id, "variables": variables }),
+        )
+    };
+    let (status, res) = render(json!({ "name": "Ann" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(
+        res["result"]["content"],
+        json!([
+            { "role": "system", "content": "You greet Ann." },
+            { "role": "user", "content": "I am Ann" },
+        ])
+    );
+    let (status, res) = render(json!({ "name": "Ann", "mood": "glad" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    assert_eq!(res["result"][0]["kind"], "unknown");
+}
+
+#[tokio::test]
+async fn chats_diff_per_message() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("chat").await;
+    let first = app.commit(id, chat("Hello")).await;
+    let second = app
+        .commit(
+            id,
+            json!([
+                { "role": "system", "content": "You greet {{ name }}." },
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hey" },
+            ]),
+        )
+        .await;
+
+    let (status, res) = app
+        .post(
+            "/prompt/diff",
+            json!({
+                "prompt_id": id,
+                "left": { "version": "v1", "commit_id": first },
+                "right": { "version": "v1", "commit_id": second },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(
+        res["result"],
+        " @@ #0 system @@\n You greet {{ name }}.\n @@ #1 user @@\n-Hello\n+Hi\n+@@ #2 assistant @@\n+Hey\n"
+    );
+}
@@ -0,0 +1,163 @@
+use std::{path::PathBuf, sync::Arc};
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use super::TestApp;
+use crate::store::FsStore;
+
+/// A test app storing contents under a fresh directory in the temp dir.
+async fn fs_app() -> (TestApp, PathBuf) {
+    let root = std::env::temp_dir().join(format!("prompt-shelf-{}", Uuid::new_v4()));
+    let app = TestApp::with_store(Arc::new(FsStore::new(&root))).await;
+    (app, root)
+}
+
+#[tokio::test]
+async fn commit_then_read_latest_and_content() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let first = app.commit(id, json!("Hello {{ name }}\n")).await;
+    let second = app.commit(id, json!("Hi {{ name }}\n")).await;
+
+    let (status, res) = app.get(&format!("/prompt/latest?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["version"], "v1");
+    assert_eq!(res["result"]["commit"]["commit_id"], second.as_str());
+    assert_eq!(res["result"]["content"], "Hi {{ name }}\n");
+
+    let (status, res) = app
+        .get(&format!(
+            "/prompt/content?prompt_id={id}&version=v1&commit_id={first}"
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], "Hello {{ name }}\n");
+    assert_eq!(res["result"]["content_type"], "plain");
+
+    let (status, res) = app
+        .get(&format!("/prompt/list_commit?prompt_id={id}&version=v1"))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"], json!([first, second]));
+}
+
+#[tokio::test]
+async fn commit_to_missing_version_is_not_found() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_commit",
+            json!({
+                "prompt_id": id,
+                "version": "v2",
+                "desp": "edit",
+                "content": "text",
+                "as_latest": false,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+}
+
+#[tokio::test]
+async fn other_users_cannot_see_a_prompt() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, _) = app
+        .call_as(
+            2,
+            Method::GET,
+            &format!("/prompt/latest?prompt_id={id}"),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn version_without_commit_is_rejected() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    app.commit(id, json!("Hello\n")).await;
+    let (status, res) = app
+        .get(&format!("/prompt/content?prompt_id={id}&version=v1"))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    let (status, res) = app
+        .post(
+            "/prompt/render",
+            json!({ "prompt_id": id, "commit_id": "c" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+}
+
+#[tokio::test]
+async fn invalid_content_reports_its_location() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_commit",
+            json!({
+                "prompt_id": id,
+                "version": "v1",
+                "desp": "edit",
+                "content": "{\n  \"a\": \n}",
+                "content_type": "json",
+                "as_latest": false,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    assert_eq!(res["result"]["line"], 3);
+    assert_eq!(res["result"]["column"], 1);
+    assert_eq!(
+        res["result"]["message"],
+        "Invalid json content, expected value"
+    );
+}
+
+#[tokio::test]
+async fn overlong_version_is_rejected() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_node",
+            json!({ "prompt_id": id, "version": "v".repeat(33) }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+}
+
+#[tokio::test]
+async fn fs_prompt_without_commits_can_be_deleted() {
+    let (app, root) = fs_app().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = app
+        .call_as(1, Method::DELETE, &format!("/prompt?prompt_id={id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let (status, _) = app.get(&format!("/prompt/latest?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[tokio::test]
+async fn fs_delete_removes_commit_files() {
+    let (app, root) = fs_app().await;
+    let id = app.prompt_with_version("greeting").await;
+    app.commit(id, json!("Hello\n")).await;
+    assert_eq!(std::fs::read_dir(&root).unwrap().count(), 1);
+
+    let (status, res) = app
+        .call_as(1, Method::DELETE, &format!("/prompt?prompt_id={id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+    let _ = std::fs::remove_dir_all(root);
+}
@@ -0,0 +1,219 @@
+use axum::http::{Method, StatusCode};
+use serde_json::{Value, json};
+
+use super::TestApp;
+
+async fn try_commit(app: &TestApp, prompt_id: u64, content: &str) -> (StatusCode, Value) {
+    app.post(
+        "/prompt/create_commit",
+        json!({
+            "prompt_id": prompt_id,
+            "version": "v1",
+            "desp": "edit",
+            "content": content,
+            "as_latest": true,
+        }),
+    )
+    .await
+}
+
+async fn latest_content(app: &TestApp, prompt_id: u64) -> Value {
+    let (status, res) = app
+        .get(&format!("/prompt/latest?prompt_id={prompt_id}"))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    res["result"]["content"].clone()
+}
+
+#[tokio::test]
+async fn a_prompt_may_include_a_fixed_commit_of_itself() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let base = app.commit(id, json!("Hello")).await;
+
+    let (status, res) = try_commit(&app, id, &format!("{{{{> {id}@v1/{base} }}}} again")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(latest_content(&app, id).await, "Hello again");
+
+    // the new commit becomes latest, so a latest include would be itself
+    let (status, res) = try_commit(&app, id, &format!("{{{{> {id} }}}}")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    let (status, res) = try_commit(&app, id, &format!("{{{{> {id}@latest }}}}")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+}
+
+#[tokio::test]
+async fn include_cycles_through_labels_are_rejected() {
+    let app = TestApp::new().await;
+    let a = app.prompt_with_version("a").await;
+    let b = app.prompt_with_version("b").await;
+    app.commit(a, json!("A")).await;
+    app.commit(b, json!(format!("B {{{{> {a} }}}}"))).await;
+
+    let (status, res) = try_commit(&app, a, &format!("A {{{{> {b} }}}}")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    assert!(
+        res["msg"].as_str().unwrap().contains("Include cycle"),
+        "{res}"
+    );
+}
+
+#[tokio::test]
+async fn includes_expand_on_latest_and_render() {
+    let app = TestApp::new().await;
+    let sig = app.prompt_with_version("signature").await;
+    let first = app.commit(sig, json!("Bye")).await;
+    app.commit(sig, json!("Regards")).await;
+    app.post(
+        "/prompt/label/set",
+        json!({ "prompt_id": sig, "label": "prod", "version": "v1", "commit_id": first }),
+    )
+    .await;
+    let mail = app.prompt_with_version("mail").await;
+    let content = format!("Hi {{{{ name }}}}. {{{{> {sig} }}}}, {{{{> {sig}@prod }}}}");
+    let (status, res) = try_commit(&app, mail, &content).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+
+    assert_eq!(
+        latest_content(&app, mail).await,
+        "Hi {{ name }}. Regards, Bye"
+    );
+    let (status, res) = app
+        .post(
+            "/prompt/render",
+            json!({ "prompt_id": mail, "variables": { "name": "Ann" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], "Hi Ann. Regards, Bye");
+    // the raw commit keeps its directives
+    let (status, res) = app.get(&format!("/prompt/content?prompt_id={mail}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], content);
+}
+
+#[tokio::test]
+async fn includes_nest_at_most_eight_levels() {
+    let app = TestApp::new().await;
+    let mut prev = app.prompt_with_version("p0").await;
+    app.commit(prev, json!("end")).await;
+    for level in 1..=8 {
+        let id = app.prompt_with_version(&format!("p{level}")).await;
+        let (status, res) = try_commit(&app, id, &format!("{{{{> {prev} }}}}")).await;
+        assert_eq!(status, StatusCode::OK, "level {level}: {res}");
+        prev = id;
+    }
+    assert_eq!(latest_content(&app, prev).await, "end");
+
+    let id = app.prompt_with_version("p9").await;
+    let (status, res) = try_commit(&app, id, &format!("{{{{> {prev} }}}}")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+}
+
+#[tokio::test]
+async fn includes_need_view_access() {
+    let app = TestApp::new().await;
+    let private = app.prompt_with_version("private").await;
+    app.commit(private, json!("secret")).await;
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/create_prompt",
+            Some(json!({ "name": "mine" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let mine = res["result"]["id"].as_u64().unwrap();
+    app.call_as(
+        2,
+        Method::POST,
+        "/prompt/create_node",
+        Some(json!({ "prompt_id": mine, "version": "v1" })),
+    )
+    .await;
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/create_commit",
+            Some(json!({
+                "prompt_id": mine,
+                "version": "v1",
+                "desp": "edit",
+                "content": format!("{{{{> {private} }}}}"),
+                "as_latest": true,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+}
+
+#[tokio::test]
+async fn included_by_lists_only_visible_includers() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let shared = app.prompt_with_version("shared").await;
+    app.commit(shared, json!("common")).await;
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": shared, "user_id": 2, "role": "viewer" }),
+    )
+    .await;
+    let own = app.prompt_with_version("own").await;
+    let own_commit = app.commit(own, json!(format!("{{{{> {shared} }}}}"))).await;
+
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/create_prompt",
+            Some(json!({ "name": "theirs" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let theirs = res["result"]["id"].as_u64().unwrap();
+    app.call_as(
+        2,
+        Method::POST,
+        "/prompt/create_node",
+        Some(json!({ "prompt_id": theirs, "version": "v1" })),
+    )
+    .await;
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/create_commit",
+            Some(json!({
+                "prompt_id": theirs,
+                "version": "v1",
+                "desp": "edit",
+                "content": format!("{{{{> {shared} }}}}"),
+                "as_latest": false,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+
+    let uri = format!("/prompt/included_by?prompt_id={shared}");
+    let (status, res) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(
+        res["result"],
+        json!([{
+            "prompt_id": own,
+            "name": "own",
+            "version": "v1",
+            "commit_id": own_commit,
+            "reference": shared.to_string(),
+            "labels": ["latest"],
+        }])
+    );
+    let (status, res) = app.call_as(2, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let includers = res["result"].as_array().unwrap();
+    assert_eq!(includers.len(), 1);
+    assert_eq!(includers[0]["prompt_id"], theirs);
+    assert_eq!(includers[0]["labels"], json!([]));
+}
@@ -0,0 +1,269 @@
+//! A proxy connection running the MySQL-flavoured entities on in-memory
+//! SQLite. sea-orm only decodes `u64` from MySQL rows, so each row is turned
+//! back into values of the column types the entities declare.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ColumnTrait, ColumnType, Database, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    IdenStatic, Iterable, ProxyDatabaseTrait, ProxyExecResult, ProxyRow, Schema, Statement, Value,
+    prelude::async_trait::async_trait,
+    sqlx::{
+        self, Connection, Row, Sqlite, SqliteConnection, ValueRef,
+        query::Query,
+        sqlite::{SqliteArguments, SqliteQueryResult, SqliteRow},
+    },
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::db::prelude::*;
+
+#[derive(Debug)]
+struct SqliteProxy {
+    conn: Mutex<SqliteConnection>,
+    /// Column types by table and column name.
+    columns: HashMap<String, HashMap<String, ColumnType>>,
+    /// Set when a transaction is dropped without commit; rolled back before
+    /// the next statement since `start_rollback` cannot wait.
+    rollback: AtomicBool,
+}
+
+fn db_err(e: sqlx::Error) -> DbErr {
+    DbErr::Custom(e.to_string())
+}
+
+fn bind<'q>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    value: Value,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    match value {
+        Value::Bool(v) => query.bind(v),
+        Value::TinyInt(v) => query.bind(v),
+        Value::SmallInt(v) => query.bind(v),
+        Value::Int(v) => query.bind(v),
+        Value::BigInt(v) => query.bind(v),
+        Value::TinyUnsigned(v) => query.bind(v),
+        Value::SmallUnsigned(v) => query.bind(v),
+        Value::Unsigned(v) => query.bind(v),
+        Value::BigUnsigned(v) => query.bind(v.map(|v| v as i64)),
+        Value::Float(v) => query.bind(v),
+        Value::Double(v) => query.bind(v),
+        Value::String(v) => query.bind(v.map(|v| *v)),
+        Value::Char(v) => query.bind(v.map(String::from)),
+        Value::Bytes(v) => query.bind(v.map(|v| *v)),
+        Value::Json(v) => query.bind(v.map(|v| v.to_string())),
+        // the same shape as `CURRENT_TIMESTAMP` so stored times compare as text
+        Value::ChronoDateTimeUtc(v) => {
+            query.bind(v.map(|v| v.format("%Y-%m-%d %H:%M:%S%.f").to_string()))
+        }
+        other => panic!("unsupported value in test query: {other:?}"),
+    }
+}
+
+fn prepare(statement: &Statement) -> Query<'_, Sqlite, SqliteArguments<'_>> {
+    let values = statement.values.iter().flat_map(|v| v.0.iter().cloned());
+    values.fold(sqlx::query(&statement.sql), bind)
+}
+
+/// Tables named in a statement generated by sea-query.
+fn tables(sql: &str) -> Vec<&str> {
+    let mut found = Vec::new();
+    for keyword in ["FROM \"", "JOIN \"", "INTO \"", "UPDATE \""] {
+        for (at, _) in sql.match_indices(keyword) {
+            let rest = &sql[at + keyword.len()..];
+            if let Some(end) = rest.find('"') {
+                found.push(&rest[..end]);
+            }
+        }
+    }
+    found
+}
+
+impl SqliteProxy {
+    fn column_type(&self, tables: &[&str], column: &str) -> Option<&ColumnType> {
+        tables
+            .iter()
+            .filter_map(|table| self.columns.get(*table))
+            .find_map(|columns| columns.get(column))
+    }
+
+    fn decode(&self, row: &SqliteRow, tables: &[&str]) -> Result<ProxyRow, DbErr> {
+        let mut values = BTreeMap::new();
+        for (idx, column) in row.columns().iter().enumerate() {
+            let name = sqlx::Column::name(column);
+            let raw = row.try_get_raw(idx).map_err(db_err)?;
+            let value = match self.column_type(tables, name) {
+                Some(ColumnType::BigUnsigned) => Value::BigUnsigned(
+                    row.try_get::<Option<i64>, _>(idx)
+                        .map_err(db_err)?
+                        .map(|v| v as u64),
+                ),
+                Some(ColumnType::Unsigned) => {
+                    Value::Unsigned(row.try_get::<Option<u32>, _>(idx).map_err(db_err)?)
+                }
+                Some(ColumnType::BigInteger) => Value::BigInt(row.try_get(idx).map_err(db_err)?),
+                Some(ColumnType::Integer) => Value::Int(row.try_get(idx).map_err(db_err)?),
+                Some(ColumnType::SmallInteger) => {
+                    Value::SmallInt(row.try_get(idx).map_err(db_err)?)
+                }
+                Some(ColumnType::TinyInteger) => Value::TinyInt(row.try_get(idx).map_err(db_err)?),
+                Some(ColumnType::Boolean) => Value::Bool(row.try_get(idx).map_err(db_err)?),
+                Some(ColumnType::Float | ColumnType::Double | ColumnType::Decimal(_)) => {
+                    Value::Double(row.try_get(idx).map_err(db_err)?)
+                }
+                Some(
+                    ColumnType::Timestamp
+                    | ColumnType::TimestampWithTimeZone
+                    | ColumnType::DateTime,
+                ) => Value::ChronoDateTimeUtc(
+                    row.try_get::<Option<DateTime<Utc>>, _>(idx)
+                        .map_err(db_err)?
+                        .map(Box::new),
+                ),
+                Some(ColumnType::Json | ColumnType::JsonBinary) => Value::Json(
+                    row.try_get::<Option<String>, _>(idx)
+                        .map_err(db_err)?
+                        .map(|v| serde_json::from_str(&v).map(Box::new))
+                        .transpose()
+                        .map_err(|e| DbErr::Custom(e.to_string()))?,
+                ),
+                Some(_) => Value::String(
+                    row.try_get::<Option<String>, _>(idx)
+                        .map_err(db_err)?
+                        .map(Box::new),
+                ),
+                // aggregates and aliases outside the entities
+                None if raw.is_null() => Value::BigInt(None),
+                // the paginator reads its count as `i32` off Postgres
+                None if name == "num_items" => Value::Int(row.try_get(idx).map_err(db_err)?),
+                None => row
+                    .try_get::<i64, _>(idx)
+                    .map(Value::from)
+                    .or_else(|_| row.try_get::<f64, _>(idx).map(Value::from))
+                    .or_else(|_| row.try_get::<String, _>(idx).map(Value::from))
+                    .map_err(db_err)?,
+            };
+            values.insert(name.to_string(), value);
+        }
+        Ok(ProxyRow::new(values))
+    }
+
+    /// Locks the connection, first finishing a rollback left by a dropped
+    /// transaction.
+    async fn lock(&self) -> Result<MutexGuard<'_, SqliteConnection>, DbErr> {
+        let mut conn = self.conn.lock().await;
+        if self.rollback.swap(false, Ordering::SeqCst) {
+            sqlx::query("ROLLBACK")
+                .execute(&mut *conn)
+                .await
+                .map_err(db_err)?;
+        }
+        Ok(conn)
+    }
+
+    async fn run(&self, statement: &Statement) -> Result<SqliteQueryResult, DbErr> {
+        let mut conn = self.lock().await?;
+        prepare(statement).execute(&mut *conn).await.map_err(db_err)
+    }
+}
+
+#[async_trait]
+impl ProxyDatabaseTrait for SqliteProxy {
+    async fn query(&self, statement: Statement) -> Result<Vec<ProxyRow>, DbErr> {
+        let mut conn = self.lock().await?;
+        let rows = prepare(&statement)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(db_err)?;
+        let tables = tables(&statement.sql);
+        rows.iter().map(|row| self.decode(row, &tables)).collect()
+    }
+
+    async fn execute(&self, statement: Statement) -> Result<ProxyExecResult, DbErr> {
+        let res = self.run(&statement).await?;
+        Ok(ProxyExecResult::new(
+            res.last_insert_rowid() as u64,
+            res.rows_affected(),
+        ))
+    }
+
+    async fn begin(&self) {
+        self.run(&Statement::from_string(DbBackend::Sqlite, "BEGIN"))
+            .await
+            .unwrap();
+    }
+
+    async fn commit(&self) {
+        self.run(&Statement::from_string(DbBackend::Sqlite, "COMMIT"))
+            .await
+            .unwrap();
+    }
+
+    async fn rollback(&self) {
+        self.run(&Statement::from_string(DbBackend::Sqlite, "ROLLBACK"))
+            .await
+            .unwrap();
+    }
+
+    fn start_rollback(&self) {
+        self.rollback.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Schema and column types of the tables the routes use.
+#[derive(Default)]
+struct Tables {
+    ddl: Vec<Statement>,
+    columns: HashMap<String, HashMap<String, ColumnType>>,
+}
+
+impl Tables {
+    fn add<E: EntityTrait>(mut self, entity: E) -> Self {
+        let schema = Schema::new(DbBackend::Sqlite);
+        self.ddl
+            .push(DbBackend::Sqlite.build(&schema.create_table_from_entity(entity)));
+        self.columns.insert(
+            entity.table_name().to_string(),
+            E::Column::iter()
+                .map(|c| (c.as_str().to_string(), c.def().get_column_type().clone()))
+                .collect(),
+        );
+        self
+    }
+}
+
+pub async fn sqlite() -> DatabaseConnection {
+    let tables = Tables::default()
+        .add(Users)
+        .add(Organizations)
+        .add(UserOrganizations)
+        .add(Prompts)
+        .add(PromptVersions)
+        .add(PromptCommits)
+        .add(PromptLabels)
+        .add(PromptPromotions)
+        .add(PromptIncludes)
+        .add(PromptPermissions);
+    let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+    for statement in &tables.ddl {
+        sqlx::query(&statement.sql)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+    let proxy = SqliteProxy {
+        conn: Mutex::new(conn),
+        columns: tables.columns,
+        rollback: AtomicBool::new(false),
+    };
+    Database::connect_proxy(DbBackend::Sqlite, Arc::new(Box::new(proxy)))
+        .await
+        .unwrap()
+}
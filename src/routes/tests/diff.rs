@@ -0,0 +1,199 @@
+use axum::http::{Method, StatusCode};
+use sea_orm::EntityTrait;
+use serde_json::{Value, json};
+
+use super::TestApp;
+use crate::db::prelude::Prompts;
+
+/// Commits `left` then `right` on a new prompt and returns the prompt id and
+/// both commit ids.
+async fn two_commits(app: &TestApp, left: Value, right: Value) -> (u64, String, String) {
+    let id = app.prompt_with_version("greeting").await;
+    let first = app.commit(id, left).await;
+    let second = app.commit(id, right).await;
+    (id, first, second)
+}
+
+fn diff_request(id: u64, left: &str, right: &str) -> Value {
+    json!({
+        "prompt_id": id,
+        "left": { "version": "v1", "commit_id": left },
+        "right": { "version": "v1", "commit_id": right },
+    })
+}
+
+#[tokio::test]
+async fn diff_reports_missing_content() {
+    let app = TestApp::new().await;
+    let (id, first, second) = two_commits(&app, json!("a\n"), json!("b\n")).await;
+    let (status, res) = app
+        .post("/prompt/diff", diff_request(id, &first, &second))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"], "-a\n+b\n");
+
+    let prompt = Prompts::find_by_id(id)
+        .one(&app.state.sql_conn)
+        .await
+        .unwrap()
+        .unwrap();
+    app.state
+        .store
+        .delete_prompt(&prompt.file_key)
+        .await
+        .unwrap();
+    let (status, res) = app
+        .post("/prompt/diff", diff_request(id, &first, &second))
+        .await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{res}");
+}
+
+#[tokio::test]
+async fn diff_with_context_applies_as_a_patch() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("chat").await;
+    let mut commits = Vec::new();
+    for (text, model) in [("Hello\nthere", "m1"), ("Hi\nthere", "m2")] {
+        let (status, res) = app
+            .post(
+                "/prompt/create_commit",
+                json!({
+                    "prompt_id": id,
+                    "version": "v1",
+                    "desp": "edit",
+                    "content": [
+                        { "role": "system", "content": "Be brief." },
+                        { "role": "user", "content": text },
+                    ],
+                    "model_config": { "model": model },
+                    "as_latest": true,
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{res}");
+        commits.push(res["result"]["commit_id"].as_str().unwrap().to_string());
+    }
+
+    // back from the head to the first commit
+    let mut request = diff_request(id, &commits[1], &commits[0]);
+    request["context"] = json!(1);
+    let (status, res) = app.post("/prompt/diff", request).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let patch = res["result"].as_str().unwrap();
+    assert!(!patch.contains("model_config"), "{patch}");
+
+    let (status, res) = app
+        .post(
+            "/prompt/apply_patch",
+            json!({
+                "prompt_id": id,
+                "version": "v1",
+                "commit_id": commits[1],
+                "patch": patch,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let applied = res["result"]["commit_id"].as_str().unwrap();
+    let uri =
+        |commit: &str| format!("/prompt/content?prompt_id={id}&version=v1&commit_id={commit}");
+    let (_, expected) = app.get(&uri(&commits[0])).await;
+    let (_, res) = app.get(&uri(applied)).await;
+    assert_eq!(res["result"]["content"], expected["result"]["content"]);
+}
+
+#[tokio::test]
+async fn diff_modes_reach_the_route() {
+    let app = TestApp::new().await;
+    let (id, first, second) =
+        two_commits(&app, json!("Say Hello\nBye\n"), json!("Say hi\nbye\n")).await;
+    let mut request = diff_request(id, &first, &second);
+    request["ignore_case"] = json!(true);
+    request["granularity"] = json!("word");
+    request["format"] = json!("json");
+    let (status, res) = app.post("/prompt/diff", request).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(
+        res["result"]["content"]["stats"],
+        json!({ "added": 1, "removed": 1 })
+    );
+    let lines = &res["result"]["content"]["hunks"][0]["lines"];
+    assert_eq!(lines[1]["op"], "insert");
+    assert_eq!(lines[1]["highlights"], json!([{ "start": 4, "end": 6 }]));
+
+    let mut request = diff_request(id, &first, &second);
+    request["ignore_case"] = json!(true);
+    let (status, res) = app.post("/prompt/diff", request).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"], "-Say Hello\n+Say hi\n bye\n");
+}
+
+#[tokio::test]
+async fn patches_need_exact_lines() {
+    let app = TestApp::new().await;
+    let (id, first, second) = two_commits(&app, json!("a\n"), json!("A\n")).await;
+    let mut request = diff_request(id, &first, &second);
+    request["context"] = json!(1);
+    request["ignore_case"] = json!(true);
+    let (status, res) = app.post("/prompt/diff", request.clone()).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+
+    request["format"] = json!("json");
+    let (status, res) = app.post("/prompt/diff", request).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(
+        res["result"]["content"]["stats"],
+        json!({ "added": 0, "removed": 0 })
+    );
+}
+
+#[tokio::test]
+async fn cross_prompt_diff_needs_read_on_both_sides() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let (shared, shared_commit, _) = two_commits(&app, json!("a\n"), json!("b\n")).await;
+    let (private, private_commit, _) = two_commits(&app, json!("c\n"), json!("d\n")).await;
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": shared, "user_id": 2, "role": "viewer" }),
+    )
+    .await;
+    let side =
+        |id: u64, commit: &str| json!({ "prompt_id": id, "version": "v1", "commit_id": commit });
+
+    let request = json!({
+        "left": side(shared, &shared_commit),
+        "right": side(private, &private_commit),
+    });
+    let (status, res) = app
+        .call_as(2, Method::POST, "/prompt/diff", Some(request.clone()))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+    let (status, res) = app.post("/prompt/diff", request).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"], "-a\n+c\n");
+
+    let request = json!({
+        "left": side(private, &private_commit),
+        "right": side(shared, &shared_commit),
+    });
+    let (status, res) = app
+        .call_as(2, Method::POST, "/prompt/diff", Some(request))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": private, "user_id": 2, "role": "viewer" }),
+    )
+    .await;
+    let request = json!({
+        "left": side(private, &private_commit),
+        "right": side(shared, &shared_commit),
+    });
+    let (status, res) = app
+        .call_as(2, Method::POST, "/prompt/diff", Some(request))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"], "-c\n+a\n");
+}
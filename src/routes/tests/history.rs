@@ -0,0 +1,282 @@
+use axum::http::{Method, StatusCode};
+use serde_json::{Value, json};
+
+use super::TestApp;
+
+/// Commits `content` on `version` without moving `latest`.
+async fn commit_on(app: &TestApp, prompt_id: u64, version: &str, content: &str) -> String {
+    let (status, res) = app
+        .post(
+            "/prompt/create_commit",
+            json!({
+                "prompt_id": prompt_id,
+                "version": version,
+                "desp": "edit",
+                "content": content,
+                "as_latest": false,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    res["result"]["commit_id"].as_str().unwrap().to_string()
+}
+
+async fn content_of(app: &TestApp, prompt_id: u64, version: &str, commit_id: &str) -> Value {
+    let (status, res) = app
+        .get(&format!(
+            "/prompt/content?prompt_id={prompt_id}&version={version}&commit_id={commit_id}"
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    res["result"]["content"].clone()
+}
+
+/// A prompt with `v1` at "a\nb\nc\n" and `v2` branched from it.
+async fn two_versions(app: &TestApp) -> (u64, String) {
+    let id = app.prompt_with_version("greeting").await;
+    let base = commit_on(app, id, "v1", "a\nb\nc\n").await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_node",
+            json!({ "prompt_id": id, "version": "v2", "from_commit": base }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    (id, base)
+}
+
+#[tokio::test]
+async fn cherry_pick_copies_a_change_between_versions() {
+    let app = TestApp::new().await;
+    let (id, _) = two_versions(&app).await;
+    let fix = commit_on(&app, id, "v2", "a\nB\nc\n").await;
+    let head = commit_on(&app, id, "v1", "x\na\nb\nc\n").await;
+
+    let (status, res) = app
+        .post(
+            "/prompt/cherry_pick",
+            json!({ "prompt_id": id, "commit_id": fix, "version": "v1" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let picked = res["result"]["commit_id"].as_str().unwrap().to_string();
+    assert_eq!(content_of(&app, id, "v1", &picked).await, "x\na\nB\nc\n");
+    let (_, res) = app.get(&format!("/prompt/query?id={id}")).await;
+    let commits = &res["result"][0]["prompt"]["nodes"][0]["commits"];
+    let last = &commits[commits.as_array().unwrap().len() - 1];
+    assert_eq!(last["commit_id"], picked.as_str());
+    assert_eq!(last["parents"], json!([head]));
+}
+
+#[tokio::test]
+async fn cherry_pick_reports_overlapping_edits() {
+    let app = TestApp::new().await;
+    let (id, _) = two_versions(&app).await;
+    let fix = commit_on(&app, id, "v2", "a\nB\nc\n").await;
+    commit_on(&app, id, "v1", "a\nb2\nc\n").await;
+
+    let (status, res) = app
+        .post(
+            "/prompt/cherry_pick",
+            json!({ "prompt_id": id, "commit_id": fix, "version": "v1" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{res}");
+    assert_eq!(
+        res["result"],
+        json!([{ "line": 2, "base": "b\n", "ours": "b2\n", "theirs": "B\n" }])
+    );
+    let (_, res) = app
+        .get(&format!("/prompt/list_commit?prompt_id={id}&version=v1"))
+        .await;
+    assert_eq!(res["result"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn revert_commit_undoes_a_change() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let root = commit_on(&app, id, "v1", "a\nb\nc\n").await;
+    let typo = commit_on(&app, id, "v1", "a\nB\nc\n").await;
+    commit_on(&app, id, "v1", "a\nB\nc\nd\n").await;
+
+    let (status, res) = app
+        .post(
+            "/prompt/revert_commit",
+            json!({ "prompt_id": id, "commit_id": typo, "as_latest": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let revert = res["result"]["commit_id"].as_str().unwrap().to_string();
+    let (status, res) = app.get(&format!("/prompt/latest?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["commit"]["commit_id"], revert.as_str());
+    assert_eq!(
+        res["result"]["commit"]["desp"],
+        format!("Revert v1/{typo} (edit)")
+    );
+    assert_eq!(res["result"]["content"], "a\nb\nc\nd\n");
+
+    // the first commit has nothing to go back to
+    let (status, res) = app
+        .post(
+            "/prompt/revert_commit",
+            json!({ "prompt_id": id, "commit_id": root }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{res}");
+}
+
+#[tokio::test]
+async fn revert_commit_reports_later_edits_of_the_same_lines() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    commit_on(&app, id, "v1", "a\nb\n").await;
+    let typo = commit_on(&app, id, "v1", "a\nB\n").await;
+    commit_on(&app, id, "v1", "a\nB2\n").await;
+
+    let (status, res) = app
+        .post(
+            "/prompt/revert_commit",
+            json!({ "prompt_id": id, "commit_id": typo }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{res}");
+    assert_eq!(
+        res["result"],
+        json!([{ "line": 2, "base": "B\n", "ours": "B2\n", "theirs": "b\n" }])
+    );
+}
+
+/// `(content, commit_id)` of every line of a blamed commit.
+async fn blame(
+    app: &TestApp,
+    prompt_id: u64,
+    version: &str,
+    commit_id: &str,
+) -> Vec<(String, String)> {
+    let (status, res) = app
+        .get(&format!(
+            "/prompt/blame?prompt_id={prompt_id}&version={version}&commit_id={commit_id}"
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    res["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| {
+            (
+                l["content"].as_str().unwrap().to_string(),
+                l["commit_id"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn blame_follows_both_sides_of_a_merge() {
+    let app = TestApp::new().await;
+    let (id, base) = two_versions(&app).await;
+    let fix = commit_on(&app, id, "v2", "a\nB\nc\n").await;
+    let added = commit_on(&app, id, "v1", "a\nb\nc\nd\n").await;
+    let (status, res) = app
+        .post(
+            "/prompt/merge",
+            json!({ "prompt_id": id, "version": "v1", "source_version": "v2" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let merge = res["result"]["commit_id"].as_str().unwrap().to_string();
+
+    let owners: Vec<(&str, &str)> = vec![("a", &base), ("B", &fix), ("c", &base), ("d", &added)];
+    let found = blame(&app, id, "v1", &merge).await;
+    let found: Vec<(&str, &str)> = found
+        .iter()
+        .map(|(l, c)| (l.as_str(), c.as_str()))
+        .collect();
+    assert_eq!(found, owners);
+}
+
+#[tokio::test]
+async fn blame_attributes_lines_to_their_last_change() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let id = app.prompt_with_version("greeting").await;
+    app.post(
+        "/prompt/permission/grant",
+        json!({ "prompt_id": id, "user_id": 2, "role": "editor" }),
+    )
+    .await;
+    let first = commit_on(&app, id, "v1", "a\nb\nc").await;
+    let (status, res) = app
+        .call_as(
+            2,
+            Method::POST,
+            "/prompt/create_commit",
+            Some(json!({
+                "prompt_id": id,
+                "version": "v1",
+                "desp": "edit",
+                "content": "a\nB\nc",
+                "as_latest": false,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let second = res["result"]["commit_id"].as_str().unwrap().to_string();
+    let third = commit_on(&app, id, "v1", "a\nB\nc\nd").await;
+
+    let found = blame(&app, id, "v1", &third).await;
+    let found: Vec<(&str, &str)> = found
+        .iter()
+        .map(|(l, c)| (l.as_str(), c.as_str()))
+        .collect();
+    // the last line lost its open end in the third commit
+    assert_eq!(
+        found,
+        [
+            ("a", first.as_str()),
+            ("B", &second),
+            ("c", &third),
+            ("d", &third)
+        ]
+    );
+    let (status, res) = app
+        .get(&format!(
+            "/prompt/blame?prompt_id={id}&version=v1&commit_id={second}"
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"][1]["author"], "user2@example.com");
+    assert_eq!(res["result"][1]["line"], 2);
+    assert_eq!(res["result"][0]["author"], "user1@example.com");
+
+    let (status, res) = app
+        .get(&format!(
+            "/prompt/blame?prompt_id={id}&version=v1&commit_id=missing"
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+}
+
+#[tokio::test]
+async fn apply_patch_onto_the_base_of_a_new_version() {
+    let app = TestApp::new().await;
+    let (id, base) = two_versions(&app).await;
+    let (status, res) = app
+        .post(
+            "/prompt/apply_patch",
+            json!({
+                "prompt_id": id,
+                "version": "v2",
+                "commit_id": base,
+                "patch": "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n",
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let patched = res["result"]["commit_id"].as_str().unwrap().to_string();
+    assert_eq!(content_of(&app, id, "v2", &patched).await, "a\nB\nc\n");
+    assert_eq!(content_of(&app, id, "v1", &base).await, "a\nb\nc\n");
+}
@@ -0,0 +1,142 @@
+use axum::http::StatusCode;
+use sea_orm::EntityTrait;
+use serde_json::json;
+
+use super::TestApp;
+use crate::{
+    db::prelude::{PromptPromotions, Prompts},
+    routes::label::{LabelTarget, move_label},
+};
+
+#[tokio::test]
+async fn latest_at_needs_recorded_history() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let commit = app.commit(id, json!("Hello\n")).await;
+    let uri = format!("/prompt/latest?prompt_id={id}&at=2999-01-01T00:00:00Z");
+    let (status, res) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["commit"]["commit_id"], commit.as_str());
+
+    // a label set before its moves were recorded
+    PromptPromotions::delete_many()
+        .exec(&app.state.sql_conn)
+        .await
+        .unwrap();
+    let (status, res) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+    let (status, _) = app.get(&format!("/prompt/latest?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn set_list_and_delete_labels() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let first = app.commit(id, json!("Hello\n")).await;
+    let second = app.commit(id, json!("Hi\n")).await;
+
+    let set = |commit_id: &str| json!({ "prompt_id": id, "label": "prod", "version": "v1", "commit_id": commit_id });
+    let (status, res) = app.post("/prompt/label/set", set(&first)).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let (status, res) = app
+        .get(&format!("/prompt/content?prompt_id={id}&label=prod"))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], "Hello\n");
+
+    let (status, res) = app.post("/prompt/label/set", set(&second)).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let (status, res) = app.get(&format!("/prompt/label/list?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let labels: Vec<_> = res["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| (l["name"].clone(), l["commit_id"].clone()))
+        .collect();
+    assert_eq!(
+        labels,
+        [
+            (json!("latest"), json!(second)),
+            (json!("prod"), json!(second))
+        ]
+    );
+    let (status, res) = app
+        .get(&format!("/prompt/label/history?prompt_id={id}&label=prod"))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"][0]["prev_commit"], first.as_str());
+    assert_eq!(res["result"][0]["commit_id"], second.as_str());
+
+    let delete = json!({ "prompt_id": id, "label": "prod" });
+    let (status, res) = app.post("/prompt/label/delete", delete.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let (status, res) = app.post("/prompt/label/delete", delete).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+    let (status, res) = app
+        .get(&format!("/prompt/content?prompt_id={id}&label=prod"))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+    let (status, res) = app.get(&format!("/prompt/label/list?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn labels_are_validated() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let commit = app.commit(id, json!("Hello\n")).await;
+    let (status, res) = app
+        .post(
+            "/prompt/label/set",
+            json!({ "prompt_id": id, "label": "in prod", "version": "v1", "commit_id": commit }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    let (status, res) = app
+        .post(
+            "/prompt/label/set",
+            json!({ "prompt_id": id, "label": "prod", "version": "v1", "commit_id": "missing" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+    let (status, res) = app
+        .post(
+            "/prompt/label/delete",
+            json!({ "prompt_id": id, "label": "latest" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+}
+
+#[tokio::test]
+async fn latest_move_records_the_stored_prev() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let first = app.commit(id, json!("Hello\n")).await;
+    // read before another request moves latest
+    let stale = Prompts::find_by_id(id)
+        .one(&app.state.sql_conn)
+        .await
+        .unwrap()
+        .unwrap();
+    let second = app.commit(id, json!("Hi\n")).await;
+
+    let target = LabelTarget {
+        version: "v1".to_string(),
+        commit_id: first.clone(),
+    };
+    move_label(&app.state.sql_conn, stale, "latest", target, "test", None)
+        .await
+        .unwrap();
+    let (status, res) = app
+        .get(&format!(
+            "/prompt/label/history?prompt_id={id}&label=latest"
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"][0]["prev_commit"], second.as_str());
+    assert_eq!(res["result"][0]["commit_id"], first.as_str());
+}
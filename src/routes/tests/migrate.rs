@@ -0,0 +1,48 @@
+use sea_orm::{EntityTrait, PaginatorTrait};
+use serde_json::{Value, json};
+
+use super::TestApp;
+use crate::{
+    db::prelude::{PromptCommits, PromptVersions},
+    routes::{common::Prompts, error::ShelfError},
+};
+
+fn legacy(versions: &[&str]) -> Prompts {
+    let nodes: Vec<Value> = versions
+        .iter()
+        .enumerate()
+        .map(|(i, version)| {
+            json!({
+                "version": version,
+                "updated_at": "2025-01-01T00:00:00Z",
+                "commits": [{
+                    "author": "a@example.com",
+                    "commit_id": format!("c{i}"),
+                    "created_at": "2025-01-01T00:00:00Z",
+                    "desp": "init",
+                }],
+            })
+        })
+        .collect();
+    serde_json::from_value(json!({ "name": "legacy", "id": "p", "nodes": nodes })).unwrap()
+}
+
+#[tokio::test]
+async fn import_writes_versions_and_commits() {
+    let app = TestApp::new().await;
+    let conn = &app.state.sql_conn;
+    legacy(&["v1", "v2"]).import(conn, 1).await.unwrap();
+    assert_eq!(PromptVersions::find().count(conn).await.unwrap(), 2);
+    assert_eq!(PromptCommits::find().count(conn).await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn import_rejects_long_version_before_writing() {
+    let app = TestApp::new().await;
+    let conn = &app.state.sql_conn;
+    let long = "v".repeat(33);
+    let res = legacy(&["v1", &long]).import(conn, 1).await;
+    assert!(matches!(res, Err(ShelfError::Validation(_))));
+    assert_eq!(PromptVersions::find().count(conn).await.unwrap(), 0);
+    assert_eq!(PromptCommits::find().count(conn).await.unwrap(), 0);
+}
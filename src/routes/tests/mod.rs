@@ -0,0 +1,244 @@
+//! Route tests on in-memory SQLite, a `MemoryStore` (or any other store) and
+//! an in-process stand-in for the few Redis commands the cache uses.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, atomic::AtomicBool},
+};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Method, Request, StatusCode, header},
+};
+use sea_orm::{ActiveValue::Set, EntityTrait};
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+use tower::ServiceExt;
+
+use super::{
+    common::AppState,
+    config::{Config, StoreBackend},
+    middleware::{JwtConf, encode_jwt},
+    org, prompt,
+};
+use crate::{
+    db::{prelude::Users, users},
+    init::redis_pool,
+    store::{MemoryStore, PromptStore, S3Conf},
+};
+
+mod access;
+mod cache;
+mod chat;
+mod commits;
+mod compose;
+mod db;
+mod diff;
+mod history;
+mod label;
+mod migrate;
+mod model;
+mod orgs;
+mod render;
+
+pub const OWNER: i64 = 1;
+
+/// Reads one RESP array of bulk strings.
+async fn read_command(reader: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+    let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).await.ok()?;
+        let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+        let mut buf = vec![0; len + 2];
+        reader.read_exact(&mut buf).await.ok()?;
+        buf.truncate(len);
+        args.push(String::from_utf8(buf).ok()?);
+    }
+    Some(args)
+}
+
+/// Serves GET, SET, SETEX and DEL from a map, answering `+OK` to anything else.
+async fn fake_redis() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let values: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let values = values.clone();
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stream);
+                while let Some(args) = read_command(&mut reader).await {
+                    let reply = match args[0].to_ascii_uppercase().as_str() {
+                        "GET" => match values.lock().await.get(&args[1]) {
+                            Some(v) => format!("${}\r\n{v}\r\n", v.len()),
+                            None => "$-1\r\n".to_string(),
+                        },
+                        "SET" => {
+                            values.lock().await.insert(args[1].clone(), args[2].clone());
+                            "+OK\r\n".to_string()
+                        }
+                        "SETEX" => {
+                            values.lock().await.insert(args[1].clone(), args[3].clone());
+                            "+OK\r\n".to_string()
+                        }
+                        "DEL" => {
+                            let mut values = values.lock().await;
+                            let removed = args[1..]
+                                .iter()
+                                .filter(|key| values.remove(*key).is_some())
+                                .count();
+                            format!(":{removed}\r\n")
+                        }
+                        "PING" => "+PONG\r\n".to_string(),
+                        _ => "+OK\r\n".to_string(),
+                    };
+                    if reader.get_mut().write_all(reply.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    format!("redis://{addr}")
+}
+
+pub struct TestApp {
+    pub state: Arc<AppState>,
+    router: Router,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with_store(Arc::new(MemoryStore::default())).await
+    }
+
+    pub async fn with_store(store: Arc<dyn PromptStore>) -> Self {
+        let config = Config {
+            data_dir: String::new(),
+            store_backend: StoreBackend::Memory,
+            s3_conf: S3Conf {
+                bucket: String::new(),
+                prefix: String::new(),
+                endpoint: String::new(),
+                region: String::new(),
+                access_key: String::new(),
+                secret_key: String::new(),
+                path_style: true,
+            },
+            jwt_conf: JwtConf {
+                secret: "test".to_string(),
+                expire: 1,
+            },
+        };
+        let state = Arc::new(AppState {
+            sql_conn: db::sqlite().await,
+            config,
+            allow_register: AtomicBool::new(true),
+            redis_pool: redis_pool(&fake_redis().await).await.unwrap(),
+            store,
+        });
+        let router = Router::new()
+            .nest("/prompt", prompt::routes(state.clone()))
+            .nest("/org", org::routes(state.clone()));
+        Self { state, router }
+    }
+
+    /// Sends a request as `user_id` and returns the HTTP status and JSON body.
+    pub async fn call_as(
+        &self,
+        user_id: i64,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let token = encode_jwt(
+            user_id,
+            &format!("user{user_id}@example.com"),
+            &self.state.config.jwt_conf,
+        )
+        .unwrap();
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .header(header::CONTENT_TYPE, "application/json");
+        let request = match body {
+            Some(body) => request.body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.call_as(OWNER, Method::GET, uri, None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.call_as(OWNER, Method::POST, uri, Some(body)).await
+    }
+
+    /// Inserts a user row so it can be granted roles by id.
+    pub async fn add_user(&self, id: i64) {
+        Users::insert(users::ActiveModel {
+            id: Set(id),
+            username: Set(format!("user{id}")),
+            email: Set(format!("user{id}@example.com")),
+            password_hash: Set(String::new()),
+            ..Default::default()
+        })
+        .exec(&self.state.sql_conn)
+        .await
+        .unwrap();
+    }
+
+    /// Creates a prompt with version `v1` and returns its id.
+    pub async fn prompt_with_version(&self, name: &str) -> u64 {
+        let (status, res) = self
+            .post("/prompt/create_prompt", json!({ "name": name }))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{res}");
+        let id = res["result"]["id"].as_u64().unwrap();
+        let (status, res) = self
+            .post(
+                "/prompt/create_node",
+                json!({ "prompt_id": id, "version": "v1" }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{res}");
+        id
+    }
+
+    /// Commits `content` on `v1` as latest and returns the commit id.
+    pub async fn commit(&self, prompt_id: u64, content: Value) -> String {
+        let (status, res) = self
+            .post(
+                "/prompt/create_commit",
+                json!({
+                    "prompt_id": prompt_id,
+                    "version": "v1",
+                    "desp": "edit",
+                    "content": content,
+                    "as_latest": true,
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{res}");
+        res["result"]["commit_id"].as_str().unwrap().to_string()
+    }
+}
@@ -0,0 +1,161 @@
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use super::TestApp;
+
+/// Commits "Hello" on `v1` of `prompt_id` with extra commit fields.
+async fn commit_with(app: &TestApp, prompt_id: u64, fields: Value) -> (StatusCode, Value) {
+    let mut body = json!({
+        "prompt_id": prompt_id,
+        "version": "v1",
+        "desp": "edit",
+        "content": "Hello",
+        "as_latest": true,
+    });
+    body.as_object_mut()
+        .unwrap()
+        .extend(fields.as_object().unwrap().clone());
+    app.post("/prompt/create_commit", body).await
+}
+
+#[tokio::test]
+async fn model_config_is_stored_with_the_commit() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let config = json!({
+        "model": "gpt-4o",
+        "temperature": 0.2,
+        "max_tokens": 256,
+        "stop": ["\n\n"],
+        "response_format": { "type": "json_object" },
+    });
+    let (status, res) = commit_with(&app, id, json!({ "model_config": config })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let commit_id = res["result"]["commit_id"].as_str().unwrap().to_string();
+
+    let (status, res) = app.get(&format!("/prompt/latest?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["commit"]["model_config"], config);
+    let (status, res) = app
+        .get(&format!(
+            "/prompt/content?prompt_id={id}&version=v1&commit_id={commit_id}"
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["model_config"], config);
+}
+
+#[tokio::test]
+async fn bad_model_config_is_rejected() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    for config in [
+        json!({ "temperature": 3.0 }),
+        json!({ "max_tokens": 0 }),
+        json!({ "model": " " }),
+        json!({ "stop": [""] }),
+        json!({ "response_format": { "type": "json_schema", "json_schema": "x" } }),
+    ] {
+        let (status, res) = commit_with(&app, id, json!({ "model_config": config })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{config}: {res}");
+    }
+    let (status, res) = app
+        .get(&format!("/prompt/list_commit?prompt_id={id}&version=v1"))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"], json!([]));
+}
+
+#[tokio::test]
+async fn diff_shows_model_config_changes() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let mut commits = Vec::new();
+    for model in ["m1", "m2"] {
+        let (status, res) =
+            commit_with(&app, id, json!({ "model_config": { "model": model } })).await;
+        assert_eq!(status, StatusCode::OK, "{res}");
+        commits.push(res["result"]["commit_id"].as_str().unwrap().to_string());
+    }
+    let (status, res) = app
+        .post(
+            "/prompt/diff",
+            json!({
+                "prompt_id": id,
+                "left": { "version": "v1", "commit_id": commits[0] },
+                "right": { "version": "v1", "commit_id": commits[1] },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(
+        res["result"],
+        " Hello\n @@ model_config @@\n {\n-  \"model\": \"m1\"\n+  \"model\": \"m2\"\n }\n"
+    );
+}
+
+fn answer_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": { "answer": { "type": "string" } },
+        "required": ["answer"],
+    })
+}
+
+#[tokio::test]
+async fn bad_output_schema_is_rejected() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = commit_with(
+        &app,
+        id,
+        json!({ "output_schema": { "type": "not-a-type" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    let (status, res) = commit_with(&app, id, json!({ "output_schema": answer_schema() })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let (status, res) = app.get(&format!("/prompt/latest?prompt_id={id}")).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["commit"]["output_schema"], answer_schema());
+}
+
+#[tokio::test]
+async fn validate_output_checks_against_the_commit_schema() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let plain = app.commit(id, json!("Hello")).await;
+    let (status, res) = commit_with(&app, id, json!({ "output_schema": answer_schema() })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let with_schema = res["result"]["commit_id"].as_str().unwrap().to_string();
+
+    let validate = |output: &str| {
+        app.post(
+            "/prompt/validate_output",
+            json!({ "prompt_id": id, "output": output }),
+        )
+    };
+    let (status, res) = validate(r#"{"answer": "42"}"#).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["valid"], true);
+    assert_eq!(res["result"]["commit_id"], with_schema.as_str());
+
+    let (status, res) = validate(r#"{"answer": 42}"#).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["valid"], false);
+    assert_eq!(res["result"]["errors"][0]["path"], "/answer");
+
+    let (status, res) = validate("not json").await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["valid"], false);
+    assert_eq!(res["result"]["errors"][0]["path"], "");
+
+    // an older commit without a schema
+    let (status, res) = app
+        .post(
+            "/prompt/validate_output",
+            json!({ "prompt_id": id, "version": "v1", "commit_id": plain, "output": "{}" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{res}");
+}
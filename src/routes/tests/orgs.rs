@@ -0,0 +1,19 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use super::TestApp;
+
+#[tokio::test]
+async fn adding_a_member_twice_conflicts() {
+    let app = TestApp::new().await;
+    app.add_user(2).await;
+    let (status, res) = app.post("/org/create", json!({ "name": "team" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let org_id = res["result"]["id"].as_i64().unwrap();
+
+    let member = json!({ "org_id": org_id, "user_id": 2 });
+    let (status, res) = app.post("/org/member/add", member.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let (status, res) = app.post("/org/member/add", member).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{res}");
+}
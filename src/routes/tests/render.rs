@@ -0,0 +1,86 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use super::TestApp;
+
+#[tokio::test]
+async fn render_accepts_undeclared_template_variables() {
+    let app = TestApp::new().await;
+    let id = app.prompt_with_version("greeting").await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_commit",
+            json!({
+                "prompt_id": id,
+                "version": "v1",
+                "desp": "edit",
+                "content": "{{ a }} {{ b }}",
+                "as_latest": true,
+                "variables": [{ "name": "a", "type": "string", "required": true }],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["warnings"][0]["name"], "b");
+
+    let render = |variables| {
+        app.post(
+            "/prompt/render",
+            json!({ "prompt_id": id, "variables": variables }),
+        )
+    };
+    let (status, res) = render(json!({ "a": "x", "b": "y" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], "x y");
+    let (status, res) = render(json!({ "a": "x" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+}
+
+#[tokio::test]
+async fn jinja_render_leaves_optional_variables_out() {
+    let app = TestApp::new().await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_prompt",
+            json!({ "name": "greeting", "template_engine": "jinja" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    let id = res["result"]["id"].as_u64().unwrap();
+    app.post(
+        "/prompt/create_node",
+        json!({ "prompt_id": id, "version": "v1" }),
+    )
+    .await;
+    let (status, res) = app
+        .post(
+            "/prompt/create_commit",
+            json!({
+                "prompt_id": id,
+                "version": "v1",
+                "desp": "edit",
+                "content": "Hi {{ user }}{% if title %}, {{ title }}{% endif %}",
+                "as_latest": true,
+                "variables": [{ "name": "user", "type": "string", "required": true }],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+
+    let render = |variables| {
+        app.post(
+            "/prompt/render",
+            json!({ "prompt_id": id, "variables": variables }),
+        )
+    };
+    let (status, res) = render(json!({ "user": "Ann" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], "Hi Ann");
+    let (status, res) = render(json!({ "user": "Ann", "title": "Dr" })).await;
+    assert_eq!(status, StatusCode::OK, "{res}");
+    assert_eq!(res["result"]["content"], "Hi Ann, Dr");
+    let (status, res) = render(json!({ "title": "Dr" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{res}");
+    assert_eq!(res["result"][0]["kind"], "missing");
+    assert_eq!(res["result"][0]["name"], "user");
+}
@@ -0,0 +1,56 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::fs;
+
+use super::PromptStore;
+use crate::routes::finder::{find_commit, find_config, find_prompt};
+
+/// The original on-disk layout: `<root>/<file_key>/info.json` and
+/// `<root>/<file_key>/<version>/<commit_id>`.
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[async_trait]
+impl PromptStore for FsStore {
+    async fn read_config(&self, file_key: &str) -> Result<String> {
+        let path = find_config(&self.root, file_key)?;
+        Ok(fs::read_to_string(path).await?)
+    }
+    async fn read_commit(&self, file_key: &str, version: &str, commit_id: &str) -> Result<String> {
+        let path = find_commit(&self.root, file_key, version, commit_id)?;
+        Ok(fs::read_to_string(path).await?)
+    }
+    async fn write_commit(
+        &self,
+        file_key: &str,
+        version: &str,
+        commit_id: &str,
+        content: &str,
+    ) -> Result<()> {
+        let path = find_commit(&self.root, file_key, version, commit_id)?;
+        fs::write(path, content).await?;
+        Ok(())
+    }
+    async fn delete_prompt(&self, file_key: &str) -> Result<()> {
+        let path = find_prompt(&self.root, file_key)?;
+        match fs::remove_dir_all(path).await {
+            // a prompt without commits never got a directory
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use super::PromptStore;
+
+/// Process-local store, used when no writable data volume is available.
+/// Nothing survives a restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    commits: RwLock<HashMap<(String, String, String), String>>,
+}
+
+fn commit_key(file_key: &str, version: &str, commit_id: &str) -> (String, String, String) {
+    (
+        file_key.to_string(),
+        version.to_string(),
+        commit_id.to_string(),
+    )
+}
+
+#[async_trait]
+impl PromptStore for MemoryStore {
+    async fn read_config(&self, file_key: &str) -> Result<String> {
+        Err(anyhow!("Config of {file_key} not found"))
+    }
+    async fn read_commit(&self, file_key: &str, version: &str, commit_id: &str) -> Result<String> {
+        self.commits
+            .read()
+            .await
+            .get(&commit_key(file_key, version, commit_id))
+            .cloned()
+            .ok_or_else(|| anyhow!("Commit {commit_id} not found"))
+    }
+    async fn write_commit(
+        &self,
+        file_key: &str,
+        version: &str,
+        commit_id: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.commits.write().await.insert(
+            commit_key(file_key, version, commit_id),
+            content.to_string(),
+        );
+        Ok(())
+    }
+    async fn delete_prompt(&self, file_key: &str) -> Result<()> {
+        self.commits
+            .write()
+            .await
+            .retain(|(key, _, _), _| key != file_key);
+        Ok(())
+    }
+}
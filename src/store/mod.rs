@@ -0,0 +1,42 @@
+//! Storage backends for commit contents. Version and commit metadata live in
+//! SQL; the legacy `info.json` configs are only read by the import in `migrate`.
+
+mod fs;
+mod memory;
+mod s3;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::routes::config::{Config, StoreBackend};
+
+pub use fs::FsStore;
+pub use memory::MemoryStore;
+pub use s3::{S3Conf, S3Store};
+
+/// Every content read or write of `Prompts` goes through this trait, keyed by
+/// the prompt `file_key`, the version name and the commit id.
+#[async_trait]
+pub trait PromptStore: Send + Sync {
+    async fn read_config(&self, file_key: &str) -> Result<String>;
+    async fn read_commit(&self, file_key: &str, version: &str, commit_id: &str) -> Result<String>;
+    async fn write_commit(
+        &self,
+        file_key: &str,
+        version: &str,
+        commit_id: &str,
+        content: &str,
+    ) -> Result<()>;
+    async fn delete_prompt(&self, file_key: &str) -> Result<()>;
+}
+
+pub fn from_config(config: &Config) -> Result<Arc<dyn PromptStore>> {
+    let store: Arc<dyn PromptStore> = match config.store_backend {
+        StoreBackend::Fs => Arc::new(FsStore::new(&config.data_dir)),
+        StoreBackend::Memory => Arc::new(MemoryStore::default()),
+        StoreBackend::S3 => Arc::new(S3Store::new(&config.s3_conf)?),
+    };
+    Ok(store)
+}
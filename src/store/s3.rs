@@ -0,0 +1,145 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use s3::{Bucket, Region, creds::Credentials};
+
+use super::PromptStore;
+
+#[derive(Debug, Clone)]
+pub struct S3Conf {
+    pub bucket: String,
+    pub prefix: String,
+    pub endpoint: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub path_style: bool,
+}
+
+/// Keeps the `fs` layout as object keys, `<prefix>/<file_key>/info.json` and
+/// `<prefix>/<file_key>/<version>/<commit_id>`, so replicas can share one bucket.
+pub struct S3Store {
+    bucket: Box<Bucket>,
+    prefix: String,
+}
+
+impl S3Store {
+    pub fn new(conf: &S3Conf) -> Result<Self> {
+        let region = if conf.endpoint.is_empty() {
+            conf.region.parse()?
+        } else {
+            Region::Custom {
+                region: conf.region.clone(),
+                endpoint: conf.endpoint.clone(),
+            }
+        };
+        let credentials = Credentials {
+            access_key: Some(conf.access_key.clone()),
+            secret_key: Some(conf.secret_key.clone()),
+            security_token: None,
+            session_token: None,
+            expiration: None,
+        };
+        let mut bucket = Bucket::new(&conf.bucket, region, credentials)?;
+        if conf.path_style {
+            bucket = bucket.with_path_style();
+        }
+        Ok(Self {
+            bucket,
+            prefix: conf.prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    fn key(&self, parts: &[&str]) -> String {
+        let path = parts.join("/");
+        if self.prefix.is_empty() {
+            path
+        } else {
+            format!("{}/{}", self.prefix, path)
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<String> {
+        let resp = self.bucket.get_object(key).await?;
+        match resp.status_code() {
+            200 => Ok(resp.to_string()?),
+            404 => Err(anyhow!("Object {key} not found")),
+            code => Err(anyhow!("Failed to get object {key}, status {code}")),
+        }
+    }
+
+    async fn put(&self, key: &str, content: &str) -> Result<()> {
+        let resp = self.bucket.put_object(key, content.as_bytes()).await?;
+        match resp.status_code() {
+            200..=299 => Ok(()),
+            code => Err(anyhow!("Failed to put object {key}, status {code}")),
+        }
+    }
+}
+
+#[async_trait]
+impl PromptStore for S3Store {
+    async fn read_config(&self, file_key: &str) -> Result<String> {
+        self.get(&self.key(&[file_key, "info.json"])).await
+    }
+    async fn read_commit(&self, file_key: &str, version: &str, commit_id: &str) -> Result<String> {
+        self.get(&self.key(&[file_key, version, commit_id])).await
+    }
+    async fn write_commit(
+        &self,
+        file_key: &str,
+        version: &str,
+        commit_id: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.put(&self.key(&[file_key, version, commit_id]), content)
+            .await
+    }
+    async fn delete_prompt(&self, file_key: &str) -> Result<()> {
+        let dir = format!("{}/", self.key(&[file_key]));
+        for page in self.bucket.list(dir, None).await? {
+            for object in page.contents {
+                let resp = self.bucket.delete_object(&object.key).await?;
+                if !(200..=299).contains(&resp.status_code()) {
+                    return Err(anyhow!(
+                        "Failed to delete object {}, status {}",
+                        object.key,
+                        resp.status_code()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(prefix: &str) -> S3Store {
+        S3Store::new(&S3Conf {
+            bucket: "prompts".to_string(),
+            prefix: prefix.to_string(),
+            endpoint: "http://127.0.0.1:9000".to_string(),
+            region: "us-east-1".to_string(),
+            access_key: "minio".to_string(),
+            secret_key: "minio123".to_string(),
+            path_style: true,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn keys_follow_the_fs_layout() {
+        let store = store("");
+        assert_eq!(store.key(&["abc", "info.json"]), "abc/info.json");
+        assert_eq!(store.key(&["abc", "v1", "c1"]), "abc/v1/c1");
+    }
+
+    #[test]
+    fn keys_sit_under_the_trimmed_prefix() {
+        let store = store("/shelf/prod/");
+        assert_eq!(store.key(&["abc", "info.json"]), "shelf/prod/abc/info.json");
+        assert_eq!(store.key(&["abc", "v1", "c1"]), "shelf/prod/abc/v1/c1");
+    }
+}
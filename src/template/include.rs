@@ -0,0 +1,65 @@
+//! `{{> ref }}` directives that inline the content of another prompt. `ref` is
+//! `<prompt_id>` for its `latest` label, `<prompt_id>@<label>` or
+//! `<prompt_id>@<version>/<commit_id>`.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    Latest,
+    Label(String),
+    Commit { version: String, commit_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Include {
+    /// byte range of the whole directive
+    pub span: Range<usize>,
+    /// the reference as written, e.g. `42@prod`
+    pub reference: String,
+    pub prompt_id: u64,
+    pub selector: Selector,
+}
+
+fn parse_ref(reference: &str) -> Option<(u64, Selector)> {
+    let (id, selector) = match reference.split_once('@') {
+        None => (reference, Selector::Latest),
+        Some((id, rest)) => match rest.split_once('/') {
+            None if !rest.is_empty() => (id, Selector::Label(rest.to_string())),
+            Some((version, commit_id)) if !version.is_empty() && !commit_id.is_empty() => (
+                id,
+                Selector::Commit {
+                    version: version.to_string(),
+                    commit_id: commit_id.to_string(),
+                },
+            ),
+            _ => return None,
+        },
+    };
+    Some((id.parse().ok()?, selector))
+}
+
+/// Include directives of `template` in order of appearance, or the first
+/// malformed one.
+pub fn includes(template: &str) -> Result<Vec<Include>, String> {
+    let mut res = Vec::new();
+    let mut pos = 0;
+    while let Some(open) = template[pos..].find("{{>") {
+        let start = pos + open;
+        let close = template[start..]
+            .find("}}")
+            .ok_or_else(|| format!("Unclosed include directive at byte {start}"))?;
+        let end = start + close + 2;
+        let reference = template[start + 3..end - 2].trim();
+        let (prompt_id, selector) = parse_ref(reference)
+            .ok_or_else(|| format!("Invalid include reference {reference:?}"))?;
+        res.push(Include {
+            span: start..end,
+            reference: reference.to_string(),
+            prompt_id,
+            selector,
+        });
+        pos = end;
+    }
+    Ok(res)
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+
+use minijinja::{Environment, Error, Template, UndefinedBehavior};
+use serde_json::{Map, Value};
+
+use super::{Engine, TemplateIssue, binding_issues};
+
+/// Builtin filters plus the contrib ones such as `truncate`. Undefined
+/// variables, and attributes of them, are falsy and print as empty strings.
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Chainable);
+    minijinja_contrib::add_to_environment(&mut env);
+    env
+}
+
+fn syntax_issue(e: Error) -> TemplateIssue {
+    TemplateIssue::Syntax {
+        message: e.to_string(),
+        line: e.line(),
+    }
+}
+
+pub fn check(template: &str) -> Result<(), TemplateIssue> {
+    environment()
+        .template_from_str(template)
+        .map(|_| ())
+        .map_err(syntax_issue)
+}
+
+/// Globals such as `range` or `namespace` are not variables of the prompt.
+fn used_variables(env: &Environment<'_>, tmpl: &Template<'_, '_>) -> BTreeSet<String> {
+    tmpl.undeclared_variables(false)
+        .into_iter()
+        .filter(|name| !env.globals().any(|(global, _)| global == name))
+        .collect()
+}
+
+pub fn variables(template: &str) -> Result<BTreeSet<String>, TemplateIssue> {
+    let env = environment();
+    let tmpl = env.template_from_str(template).map_err(syntax_issue)?;
+    Ok(used_variables(&env, &tmpl))
+}
+
+pub fn render(template: &str, vars: &Map<String, Value>) -> Result<String, Vec<TemplateIssue>> {
+    let env = environment();
+    let tmpl = env
+        .template_from_str(template)
+        .map_err(|e| vec![syntax_issue(e)])?;
+    let used = used_variables(&env, &tmpl);
+    let issues = binding_issues(Engine::Jinja, &used, vars);
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+    tmpl.render(vars).map_err(|e| {
+        vec![TemplateIssue::Render {
+            message: e.to_string(),
+            line: e.line(),
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn vars(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn omitted_variables_are_undefined() {
+        let template = "Hi{% if name %} {{ name }}{% endif %}, {{ mood | default('fine') }}";
+        assert_eq!(render(template, &Map::new()).unwrap(), "Hi, fine");
+        assert_eq!(
+            render(template, &vars(json!({ "name": "Ann", "mood": "glad" }))).unwrap(),
+            "Hi Ann, glad"
+        );
+        assert_eq!(render("{{ user.name }}!", &Map::new()).unwrap(), "!");
+    }
+
+    #[test]
+    fn given_variables_must_be_used() {
+        let issues = render("Hi", &vars(json!({ "name": "Ann" }))).unwrap_err();
+        assert!(matches!(&issues[..], [TemplateIssue::Unknown { name }] if name == "name"));
+    }
+}
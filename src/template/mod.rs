@@ -0,0 +1,184 @@
+//! Server-side rendering of prompt content. Each prompt picks an engine:
+//! `simple` only substitutes `{{variable}}` placeholders, `jinja` is a Jinja2
+//! compatible language with conditionals, loops and filters.
+
+pub mod include;
+mod jinja;
+pub mod schema;
+mod simple;
+
+use std::{collections::BTreeSet, fmt};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use schema::VarType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    #[default]
+    Simple,
+    Jinja,
+}
+
+impl Engine {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Engine::Simple => "simple",
+            Engine::Jinja => "jinja",
+        }
+    }
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "simple" => Some(Engine::Simple),
+            "jinja" => Some(Engine::Jinja),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A reason a template could not be compiled or rendered.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TemplateIssue {
+    /// referenced by the template but not given
+    Missing { name: String },
+    /// given but never referenced by the template
+    Unknown { name: String },
+    /// referenced by the template but missing from the variable schema
+    Undeclared { name: String },
+    /// declared in the variable schema but never referenced by the template
+    Unused { name: String },
+    /// declared more than once in the variable schema
+    Duplicate { name: String },
+    /// a value or default that does not match the declared type
+    Type { name: String, expected: VarType },
+    /// the template does not compile
+    Syntax {
+        message: String,
+        line: Option<usize>,
+    },
+    /// the template compiled but failed while rendering
+    Render {
+        message: String,
+        line: Option<usize>,
+    },
+}
+
+impl fmt::Display for TemplateIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateIssue::Missing { name } => write!(f, "missing variable {name}"),
+            TemplateIssue::Unknown { name } => write!(f, "unknown variable {name}"),
+            TemplateIssue::Undeclared { name } => write!(f, "undeclared variable {name}"),
+            TemplateIssue::Unused { name } => write!(f, "unused variable {name}"),
+            TemplateIssue::Duplicate { name } => write!(f, "duplicate variable {name}"),
+            TemplateIssue::Type { name, expected } => {
+                write!(f, "variable {name} must be a {}", expected.as_str())
+            }
+            TemplateIssue::Syntax { message, .. } | TemplateIssue::Render { message, .. } => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+/// Compiles `template` without rendering it.
+pub fn check(engine: Engine, template: &str) -> Result<(), TemplateIssue> {
+    match engine {
+        Engine::Simple => Ok(()),
+        Engine::Jinja => jinja::check(template),
+    }
+}
+
+fn variables_of(engine: Engine, template: &str) -> Result<BTreeSet<String>, TemplateIssue> {
+    match engine {
+        Engine::Simple => Ok(simple::variables(template)),
+        Engine::Jinja => jinja::variables(template),
+    }
+}
+
+/// Names of the variables any of `parts` references.
+pub fn variables(engine: Engine, parts: &[&str]) -> Result<BTreeSet<String>, TemplateIssue> {
+    let mut used = BTreeSet::new();
+    for part in parts {
+        used.append(&mut variables_of(engine, part)?);
+    }
+    Ok(used)
+}
+
+/// Renders `template` with `vars`. Every given variable must be referenced.
+/// `simple` placeholders must all be given; Jinja leaves absent variables
+/// undefined so `if` and `default` can test them.
+pub fn render(
+    engine: Engine,
+    template: &str,
+    vars: &Map<String, Value>,
+) -> Result<String, Vec<TemplateIssue>> {
+    match engine {
+        Engine::Simple => simple::render(template, vars),
+        Engine::Jinja => jinja::render(template, vars),
+    }
+}
+
+/// Renders templates sharing one set of variables, such as the messages of a
+/// chat. Every given variable must be referenced by at least one part.
+pub fn render_parts(
+    engine: Engine,
+    parts: &[&str],
+    vars: &Map<String, Value>,
+) -> Result<Vec<String>, Vec<TemplateIssue>> {
+    if let [part] = parts {
+        return render(engine, part, vars).map(|s| vec![s]);
+    }
+    let used_by = parts
+        .iter()
+        .map(|part| variables_of(engine, part))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|issue| vec![issue])?;
+    let used: BTreeSet<String> = used_by.iter().flatten().cloned().collect();
+    let issues = binding_issues(engine, &used, vars);
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+    parts
+        .iter()
+        .zip(used_by)
+        .map(|(part, used)| {
+            let vars = vars
+                .iter()
+                .filter(|(name, _)| used.contains(*name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            render(engine, part, &vars)
+        })
+        .collect()
+}
+
+fn binding_issues(
+    engine: Engine,
+    used: &BTreeSet<String>,
+    vars: &Map<String, Value>,
+) -> Vec<TemplateIssue> {
+    let mut issues: Vec<TemplateIssue> = match engine {
+        Engine::Simple => used
+            .iter()
+            .filter(|name| !vars.contains_key(*name))
+            .map(|name| TemplateIssue::Missing { name: name.clone() })
+            .collect(),
+        // only the variable schema makes a Jinja variable required
+        Engine::Jinja => Vec::new(),
+    };
+    issues.extend(
+        vars.keys()
+            .filter(|name| !used.contains(*name))
+            .map(|name| TemplateIssue::Unknown { name: name.clone() }),
+    );
+    issues
+}
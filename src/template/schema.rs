@@ -0,0 +1,216 @@
+//! Variables a commit declares. Render inputs are checked against them and
+//! clients can build forms from them.
+
+use std::collections::{BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::TemplateIssue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VarType {
+    String,
+    Number,
+    Bool,
+    List,
+    Object,
+}
+
+impl VarType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VarType::String => "string",
+            VarType::Number => "number",
+            VarType::Bool => "bool",
+            VarType::List => "list",
+            VarType::Object => "object",
+        }
+    }
+    fn accepts(self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (VarType::String, Value::String(_))
+                | (VarType::Number, Value::Number(_))
+                | (VarType::Bool, Value::Bool(_))
+                | (VarType::List, Value::Array(_))
+                | (VarType::Object, Value::Object(_))
+        )
+    }
+    /// The value of an optional variable that has no default.
+    fn empty(self) -> Value {
+        match self {
+            VarType::String => Value::String(String::new()),
+            VarType::Number => Value::from(0),
+            VarType::Bool => Value::Bool(false),
+            VarType::List => Value::Array(Vec::new()),
+            VarType::Object => Value::Object(Map::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variable {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub var_type: VarType,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Duplicate names and defaults that do not match their type.
+pub fn check(schema: &[Variable]) -> Vec<TemplateIssue> {
+    let mut seen = HashSet::new();
+    let mut issues = Vec::new();
+    for var in schema {
+        if !seen.insert(var.name.as_str()) {
+            issues.push(TemplateIssue::Duplicate {
+                name: var.name.clone(),
+            });
+        }
+        if let Some(default) = &var.default
+            && !var.var_type.accepts(default)
+        {
+            issues.push(TemplateIssue::Type {
+                name: var.name.clone(),
+                expected: var.var_type,
+            });
+        }
+    }
+    issues
+}
+
+/// Variables `used` by the template but not declared, and declared ones the
+/// template never uses.
+pub fn declaration_issues(schema: &[Variable], used: &BTreeSet<String>) -> Vec<TemplateIssue> {
+    let declared: BTreeSet<&str> = schema.iter().map(|v| v.name.as_str()).collect();
+    let mut issues: Vec<TemplateIssue> = used
+        .iter()
+        .filter(|name| !declared.contains(name.as_str()))
+        .map(|name| TemplateIssue::Undeclared { name: name.clone() })
+        .collect();
+    issues.extend(
+        declared
+            .into_iter()
+            .filter(|name| !used.contains(*name))
+            .map(|name| TemplateIssue::Unused {
+                name: name.to_string(),
+            }),
+    );
+    issues
+}
+
+/// Checks `vars` against `schema`. An absent variable takes its default; without
+/// one it is an error if required and empty otherwise. Variables the template
+/// `used` without declaring them are passed through unchecked.
+pub fn bind(
+    schema: &[Variable],
+    used: &BTreeSet<String>,
+    vars: &Map<String, Value>,
+) -> Result<Map<String, Value>, Vec<TemplateIssue>> {
+    let declared = |name: &String| schema.iter().any(|v| &v.name == name);
+    let mut issues: Vec<TemplateIssue> = vars
+        .keys()
+        .filter(|name| !declared(name) && !used.contains(*name))
+        .map(|name| TemplateIssue::Unknown { name: name.clone() })
+        .collect();
+    let mut bound: Map<String, Value> = vars
+        .iter()
+        .filter(|(name, _)| !declared(name) && used.contains(*name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    for var in schema {
+        let value = match (vars.get(&var.name), &var.default) {
+            (Some(value), _) => {
+                if !var.var_type.accepts(value) {
+                    issues.push(TemplateIssue::Type {
+                        name: var.name.clone(),
+                        expected: var.var_type,
+                    });
+                }
+                value.clone()
+            }
+            (None, Some(default)) => default.clone(),
+            (None, None) if var.required => {
+                issues.push(TemplateIssue::Missing {
+                    name: var.name.clone(),
+                });
+                continue;
+            }
+            (None, None) => var.var_type.empty(),
+        };
+        bound.insert(var.name.clone(), value);
+    }
+    if issues.is_empty() {
+        Ok(bound)
+    } else {
+        Err(issues)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn declared(name: &str, required: bool) -> Variable {
+        Variable {
+            name: name.to_string(),
+            var_type: VarType::String,
+            required,
+            default: None,
+            description: None,
+        }
+    }
+
+    fn used(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn vars(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn undeclared_used_variables_pass_through() {
+        let bound = bind(
+            &[declared("a", true)],
+            &used(&["a", "b"]),
+            &vars(json!({ "a": "x", "b": 1 })),
+        )
+        .unwrap();
+        assert_eq!(Value::Object(bound), json!({ "a": "x", "b": 1 }));
+    }
+
+    #[test]
+    fn unused_undeclared_variables_are_unknown() {
+        let issues = bind(
+            &[declared("a", false)],
+            &used(&["a"]),
+            &vars(json!({ "c": "x" })),
+        )
+        .unwrap_err();
+        assert!(matches!(&issues[..], [TemplateIssue::Unknown { name }] if name == "c"));
+    }
+
+    #[test]
+    fn declared_variables_are_checked() {
+        let issues = bind(
+            &[declared("a", true), declared("b", false)],
+            &used(&["a", "b"]),
+            &vars(json!({ "b": 1 })),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            &issues[..],
+            [TemplateIssue::Missing { name: missing }, TemplateIssue::Type { name, .. }]
+                if missing == "a" && name == "b"
+        ));
+    }
+}
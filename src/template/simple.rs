@@ -0,0 +1,76 @@
+use std::collections::BTreeSet;
+
+use serde_json::{Map, Value};
+
+use super::{Engine, TemplateIssue, binding_issues};
+
+/// A `{{ name }}` placeholder found at byte range `start..end`.
+struct Placeholder<'a> {
+    start: usize,
+    end: usize,
+    name: &'a str,
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Placeholders in order of appearance. A `{{` that does not enclose a plain
+/// identifier is left as literal text.
+fn placeholders(template: &str) -> Vec<Placeholder<'_>> {
+    let mut res = Vec::new();
+    let mut pos = 0;
+    while let Some(open) = template[pos..].find("{{") {
+        let start = pos + open;
+        let Some(close) = template[start + 2..].find("}}") else {
+            break;
+        };
+        let end = start + 2 + close + 2;
+        let name = template[start + 2..end - 2].trim();
+        if is_ident(name) {
+            res.push(Placeholder { start, end, name });
+            pos = end;
+        } else {
+            pos = start + 2;
+        }
+    }
+    res
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub fn variables(template: &str) -> BTreeSet<String> {
+    placeholders(template)
+        .into_iter()
+        .map(|p| p.name.to_string())
+        .collect()
+}
+
+/// Strings are inserted as is, other values as JSON.
+pub fn render(template: &str, vars: &Map<String, Value>) -> Result<String, Vec<TemplateIssue>> {
+    let found = placeholders(template);
+    let used = found.iter().map(|p| p.name.to_string()).collect();
+    let issues = binding_issues(Engine::Simple, &used, vars);
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+
+    let mut res = String::with_capacity(template.len());
+    let mut pos = 0;
+    for p in found {
+        res.push_str(&template[pos..p.start]);
+        res.push_str(&display(&vars[p.name]));
+        pos = p.end;
+    }
+    res.push_str(&template[pos..]);
+    Ok(res)
+}